@@ -1,11 +1,9 @@
 extern crate iron;
 extern crate guide;
-extern crate mount;
 
 use iron::{Iron, Request, Response, IronResult, method, IronError, status};
 use guide::{Router, RouteHandler, RouterError};
 use std::collections::HashMap;
-use mount::Mount;
 
 struct DummyController;
 struct DummyTwoController;
@@ -18,7 +16,7 @@ impl RouteHandler for DummyController {
                 let mut params =  HashMap::new();
                 params.insert("everybody".to_string(), "cool".to_string());
 
-                let ref query = req.extensions.get::<Router>()
+                let query = req.extensions.get::<Router>()
                     .unwrap().find("everybody").unwrap_or("/");
                 Ok(Response::with((status::Ok, format!("Ok someone {:?} {:?}", query, guide::url_for(req, route_id,params)))))
             },
@@ -42,16 +40,17 @@ impl RouteHandler for DummyTwoController {
 }
 
 fn main() {
-    let mut mount = Mount::new();
+    let mut api = Router::new();
+    api.link(DummyController);
+    api.link(DummyTwoController);
+    api.route(method::Get, "/", "handler");
+    api.post("/hello", "another");
+    api.get("/hi/:everybody", "someone");
+    api.any("/hello", "anys");
+    api.on_panic(|route_id, message| println!("Route {} panicked: {}", route_id, message));
+
     let mut router = Router::new();
-    router.link(DummyController);
-    router.link(DummyTwoController);
-    router.route(method::Get, "/", "handler");
-    router.post("/hello", "another");
-    router.get("/hi/:everybody", "someone");
-    router.any("/hello", "anys");
-    mount
-        .mount("api", router);
-    Iron::new(mount).http("localhost:3000").unwrap();
+    router.nest("/api", api).problem_details();
+    Iron::new(router).http("localhost:3000").unwrap();
 }
 
@@ -0,0 +1,333 @@
+use std::error::Error;
+use std::fmt;
+
+use iron::{method, status};
+use super::guard::Guard;
+use super::matcher::{InvalidGlob, Pattern, param_name};
+use super::static_files::StaticFiles;
+use super::router::{Conflict, RouteHandler, Router};
+use super::typed::TypedRoute;
+
+/// A route which could not be registered by a `RouterBuilder`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RegistrationError {
+    /// The route id is already registered with another glob.
+    DuplicateRouteId { route_id: String, glob: String, existing_glob: String },
+    /// The glob can't be parsed.
+    InvalidGlob(InvalidGlob),
+    /// The glob has parameters which are not in the parameter type of a typed route,
+    /// or a redirect lacks parameters of its target route.
+    MissingParams { route_id: String, params: Vec<String> },
+    /// A redirect passes catch-all parameters to parameters of its target route which can't hold a `/`.
+    CatchAllParams { route_id: String, params: Vec<String> },
+    /// A priority was set for, or a change was made to, a route id which is not registered.
+    UnknownRouteId(String),
+    /// The route belongs to a nested router, its priority and aliases are set there.
+    NestedRouteId(String),
+    /// The route can't be moved with `replace` because it is nested or localized.
+    Unreplaceable(String),
+    /// The route is shadowed by another route.
+    Conflict(Conflict)
+}
+
+impl fmt::Display for RegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegistrationError::DuplicateRouteId { ref route_id, ref glob, ref existing_glob } =>
+                write!(f, "Duplicate route_id {}: {} is already registered as {}", route_id, glob, existing_glob),
+            RegistrationError::InvalidGlob(ref e) => e.fmt(f),
+            RegistrationError::MissingParams { ref route_id, ref params } =>
+                write!(f, "Route {} has no fields for the parameters {:?}", route_id, params),
+            RegistrationError::CatchAllParams { ref route_id, ref params } =>
+                write!(f, "Route {} passes the catch-all parameters {:?} to single segments", route_id, params),
+            RegistrationError::UnknownRouteId(ref route_id) => write!(f, "No route with that ID: {}", route_id),
+            RegistrationError::NestedRouteId(ref route_id) => write!(f, "Route {} belongs to a nested router", route_id),
+            RegistrationError::Unreplaceable(ref route_id) => write!(f, "Route {} is nested or localized and can't be replaced", route_id),
+            RegistrationError::Conflict(ref c) => c.fmt(f)
+        }
+    }
+}
+
+impl Error for RegistrationError {}
+
+/// Builds a `Router` from routes which may be invalid, e.g. when they are read from a config file.
+///
+/// Unlike the methods of `Router`, which panic on invalid routes, the builder collects an error for
+/// every invalid route and returns them all from `build`. Invalid routes are skipped.
+#[derive(Default)]
+pub struct RouterBuilder {
+    router: Router,
+    errors: Vec<RegistrationError>
+}
+
+impl RouterBuilder {
+    pub fn new() -> RouterBuilder {
+        RouterBuilder::default()
+    }
+
+    pub fn link<T: RouteHandler>(&mut self, handler: T) -> &mut RouterBuilder {
+        self.router.link(handler);
+        self
+    }
+
+    pub fn route<S: AsRef<str>>(&mut self, method: method::Method, glob: S, route_id: &str) -> &mut RouterBuilder {
+        self.route_when(method, glob, route_id, Vec::new())
+    }
+
+    /// See `Router::route_when`.
+    pub fn route_when<S: AsRef<str>>(&mut self, method: method::Method, glob: S, route_id: &str, guards: Vec<Guard>) -> &mut RouterBuilder {
+        if self.check(glob.as_ref(), route_id) {
+            self.router.route_when(method, glob, route_id, guards);
+        }
+        self
+    }
+
+    /// See `Router::versioned`.
+    pub fn versioned<S: AsRef<str>>(&mut self, version: u32, method: method::Method, glob: S, route_id: &str) -> &mut RouterBuilder {
+        if self.check(glob.as_ref(), route_id) {
+            self.router.versioned(version, method, glob, route_id);
+        }
+        self
+    }
+
+    /// See `Router::typed`.
+    pub fn typed<P>(&mut self, method: method::Method, route: &TypedRoute<P>) -> &mut RouterBuilder {
+        let missing = route.missing_params();
+        if !missing.is_empty() {
+            self.errors.push(RegistrationError::MissingParams {
+                route_id: route.id().to_string(),
+                params: missing.iter().map(|p| p.to_string()).collect()
+            });
+            return self;
+        }
+
+        self.route(method, route.glob(), route.id())
+    }
+
+    pub fn any<S: AsRef<str>>(&mut self, glob: S, route_id: &str) -> &mut RouterBuilder {
+        self.any_when(glob, route_id, Vec::new())
+    }
+
+    /// See `Router::any_when`.
+    pub fn any_when<S: AsRef<str>>(&mut self, glob: S, route_id: &str, guards: Vec<Guard>) -> &mut RouterBuilder {
+        if self.check(glob.as_ref(), route_id) {
+            self.router.any_when(glob, route_id, guards);
+        }
+        self
+    }
+
+    /// See `Router::redirect`.
+    pub fn redirect<S: AsRef<str>>(&mut self, glob: S, route_id: &str, status: status::Status) -> &mut RouterBuilder {
+        let redirect_id = format!("redirect:{}", glob.as_ref());
+        if !self.check(glob.as_ref(), &redirect_id) {
+            return self;
+        }
+
+        let catch_all = self.router.catch_all_redirect_params(glob.as_ref(), route_id);
+        match self.router.missing_redirect_params(glob.as_ref(), route_id) {
+            None => self.errors.push(RegistrationError::UnknownRouteId(route_id.to_string())),
+            Some(ref missing) if !missing.is_empty() =>
+                self.errors.push(RegistrationError::MissingParams { route_id: redirect_id, params: missing.clone() }),
+            Some(_) if !catch_all.is_empty() =>
+                self.errors.push(RegistrationError::CatchAllParams { route_id: redirect_id, params: catch_all }),
+            Some(_) => { self.router.redirect(glob, route_id, status); }
+        }
+        self
+    }
+
+    /// See `Router::static_files`.
+    pub fn static_files<S: AsRef<str>, F: Into<StaticFiles>>(&mut self, glob: S, files: F) -> &mut RouterBuilder {
+        if glob.as_ref().rsplit('/').next().and_then(param_name).is_none() {
+            self.errors.push(RegistrationError::InvalidGlob(InvalidGlob {
+                glob: glob.as_ref().to_string(),
+                reason: "static file globs have to end with a parameter".to_string()
+            }));
+        } else if self.check(glob.as_ref(), &format!("static:{}", glob.as_ref())) {
+            self.router.static_files(glob, files);
+        }
+        self
+    }
+
+    /// See `Router::alias`.
+    pub fn alias<S: AsRef<str>>(&mut self, glob: S, route_id: &str) -> &mut RouterBuilder {
+        if let Err(e) = Pattern::parse(glob.as_ref()) {
+            self.errors.push(RegistrationError::InvalidGlob(e));
+        } else if self.router.glob(route_id).is_none() {
+            self.errors.push(RegistrationError::UnknownRouteId(route_id.to_string()));
+        } else if self.router.is_nested(route_id) {
+            self.errors.push(RegistrationError::NestedRouteId(route_id.to_string()));
+        } else {
+            self.router.alias(glob, route_id);
+        }
+        self
+    }
+
+    /// See `Router::priority`.
+    pub fn priority(&mut self, route_id: &str, priority: i32) -> &mut RouterBuilder {
+        if self.router.glob(route_id).is_none() {
+            self.errors.push(RegistrationError::UnknownRouteId(route_id.to_string()));
+        } else if self.router.is_nested(route_id) {
+            self.errors.push(RegistrationError::NestedRouteId(route_id.to_string()));
+        } else {
+            self.router.priority(route_id, priority);
+        }
+        self
+    }
+
+    /// See `Router::remove`.
+    pub fn remove(&mut self, route_id: &str) -> &mut RouterBuilder {
+        if !self.router.remove(route_id) {
+            self.errors.push(RegistrationError::UnknownRouteId(route_id.to_string()));
+        }
+        self
+    }
+
+    /// See `Router::replace`.
+    pub fn replace<S: AsRef<str>>(&mut self, route_id: &str, glob: S) -> &mut RouterBuilder {
+        if self.router.glob(route_id).is_none() {
+            self.errors.push(RegistrationError::UnknownRouteId(route_id.to_string()));
+        } else if !self.router.can_replace(route_id) {
+            self.errors.push(RegistrationError::Unreplaceable(route_id.to_string()));
+        } else if let Err(e) = Pattern::parse(glob.as_ref()) {
+            self.errors.push(RegistrationError::InvalidGlob(e));
+        } else {
+            self.router.replace(route_id, glob);
+        }
+        self
+    }
+
+    /// See `Router::nest`, route ids of `router` which are already registered are not added.
+    pub fn nest<S: AsRef<str>>(&mut self, prefix: S, router: Router) -> &mut RouterBuilder {
+        let prefix = prefix.as_ref().trim_matches('/');
+        let mut duplicate = false;
+
+        for (id, glob) in router.routes() {
+            let glob = format!("/{}/{}", prefix, glob.trim_start_matches('/'));
+            duplicate |= !self.check_id(&glob, id);
+        }
+
+        if !duplicate {
+            self.router.nest(prefix, router);
+        }
+        self
+    }
+
+    /// The router, or all errors found while registering routes and all conflicts between them.
+    pub fn build(self) -> Result<Router, Vec<RegistrationError>> {
+        let mut errors = self.errors;
+        errors.extend(self.router.conflicts().into_iter().map(RegistrationError::Conflict));
+
+        if errors.is_empty() {
+            Ok(self.router)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn check(&mut self, glob: &str, route_id: &str) -> bool {
+        if let Err(e) = Pattern::parse(glob) {
+            self.errors.push(RegistrationError::InvalidGlob(e));
+            return false;
+        }
+
+        self.check_id(glob, route_id)
+    }
+
+    fn check_id(&mut self, glob: &str, route_id: &str) -> bool {
+        match self.router.glob(route_id) {
+            Some(existing) if existing != glob => {
+                self.errors.push(RegistrationError::DuplicateRouteId {
+                    route_id: route_id.to_string(),
+                    glob: glob.to_string(),
+                    existing_glob: existing.to_string()
+                });
+                false
+            },
+            _ => true
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RegistrationError, RouterBuilder};
+    use iron::{method, status};
+    use router::Router;
+
+    #[test]
+    fn test_build() {
+        let mut builder = RouterBuilder::new();
+        builder.route(method::Get, "/users/:id<int>", "users.show")
+            .route(method::Put, "/users/:id<int>", "users.show")
+            .any("/health", "health")
+            .priority("health", 1)
+            .route(method::Get, "/teams", "teams.index")
+            .replace("health", "/status")
+            .remove("teams.index")
+            .alias("/members/:id<int>", "users.show")
+            .redirect("/people/:id", "users.show", status::Found)
+            .static_files("/assets/*path", "public");
+
+        let router = builder.build().unwrap();
+        assert_eq!(router.glob("users.show"), Some("/users/:id<int>"));
+        assert_eq!(router.routes(), vec![("health", "/status"), ("redirect:/people/:id", "/people/:id"),
+                                         ("static:/assets/*path", "/assets/*path"), ("users.show", "/users/:id<int>")]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut api = Router::new();
+        api.get("/users", "users.index");
+        let mut teams = Router::new();
+        teams.get("/", "teams.index");
+
+        let mut builder = RouterBuilder::new();
+        builder.route(method::Get, "/users/:id", "users.show")
+            .route(method::Get, "/people/:id", "users.show")
+            .route(method::Get, "/users/:id<float>", "users.float")
+            .route(method::Get, "/users/:name", "users.by_name")
+            .route(method::Get, "/users", "users.index")
+            .priority("missing", 1)
+            .static_files("/assets", "public")
+            .nest("/api", api)
+            .redirect("/old", "users.show", status::Found)
+            .redirect("/older", "missing", status::Found)
+            .redirect("/oldest/*id", "users.show", status::Found)
+            .nest("/teams", teams)
+            .replace("teams.index", "/groups")
+            .priority("teams.index", 1)
+            .alias("/groups", "teams.index");
+
+        // Invalid routes are skipped.
+        assert_eq!(builder.router.glob("users.show"), Some("/users/:id"));
+        assert_eq!(builder.router.glob("users.float"), None);
+
+        let errors = builder.build().err().unwrap();
+        assert_eq!(errors.len(), 12);
+        assert_eq!(errors[0], RegistrationError::DuplicateRouteId {
+            route_id: "users.show".to_string(),
+            glob: "/people/:id".to_string(),
+            existing_glob: "/users/:id".to_string()
+        });
+        assert!(matches!(errors[1], RegistrationError::InvalidGlob(_)));
+        assert_eq!(errors[2], RegistrationError::UnknownRouteId("missing".to_string()));
+        assert!(matches!(errors[3], RegistrationError::InvalidGlob(ref e) if e.glob == "/assets"));
+        assert!(matches!(errors[4], RegistrationError::DuplicateRouteId { ref glob, .. } if glob == "/api/users"));
+        assert_eq!(errors[5], RegistrationError::MissingParams {
+            route_id: "redirect:/old".to_string(),
+            params: vec!["id".to_string()]
+        });
+        assert_eq!(errors[6], RegistrationError::UnknownRouteId("missing".to_string()));
+        assert_eq!(errors[7], RegistrationError::CatchAllParams {
+            route_id: "redirect:/oldest/*id".to_string(),
+            params: vec!["id".to_string()]
+        });
+        assert_eq!(errors[8], RegistrationError::Unreplaceable("teams.index".to_string()));
+        assert_eq!(errors[9], RegistrationError::NestedRouteId("teams.index".to_string()));
+        assert_eq!(errors[10], RegistrationError::NestedRouteId("teams.index".to_string()));
+        match errors[11] {
+            RegistrationError::Conflict(ref c) => assert_eq!(c.shadowed_route_id, "users.by_name"),
+            ref e => panic!("{}", e)
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::str;
+
+use iron::{Request, headers};
+use url::form_urlencoded;
+use super::version::ApiVersion;
+
+/// The parts of a request guards are checked against.
+#[derive(Default)]
+pub struct RequestParts<'a> {
+    pub headers: Option<&'a headers::Headers>,
+    pub query: Option<&'a str>,
+    /// The API version resolved for the request, versioned routes above it don't match.
+    pub version: Option<u32>
+}
+
+impl<'a> RequestParts<'a> {
+    pub fn new(req: &'a Request) -> RequestParts<'a> {
+        RequestParts {
+            headers: Some(&req.headers),
+            query: req.url.query(),
+            version: req.extensions.get::<ApiVersion>().map(|v| v.version)
+        }
+    }
+
+    /// All comma separated values of the header `name`.
+    pub fn header_values(&self, name: &str) -> Vec<&'a str> {
+        self.headers
+            .and_then(|h| h.get_raw(name))
+            .map(|lines| lines.iter()
+                .filter_map(|line| str::from_utf8(line).ok())
+                .flat_map(|line| line.split(','))
+                .map(|value| value.trim())
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// All values of the query parameter `name`.
+    pub fn query_values(&self, name: &str) -> Vec<String> {
+        form_urlencoded::parse(self.query.unwrap_or("").as_bytes())
+            .filter(|(key, _)| *key == name)
+            .map(|(_, value)| value.into_owned())
+            .collect()
+    }
+}
+
+/// A condition a request must satisfy for a route to match.
+///
+/// Guards are checked by the router after the path and method have matched, so several
+/// routes can share one glob and method and still have different route ids.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Guard {
+    /// The query parameter is present.
+    Query(String),
+    /// The query parameter has the given value.
+    QueryEq(String, String),
+    /// The header is present.
+    Header(String),
+    /// One of the comma separated values of the header is equal to the given value.
+    HeaderEq(String, String),
+    /// The `Content-Type` of the request matches the media type, e.g. `application/json` or `multipart/*`.
+    ContentType(String),
+    /// The `Accept` header of the request allows the media type the route responds with.
+    Accept(String)
+}
+
+impl Guard {
+    pub fn query<S: Into<String>>(name: S) -> Guard {
+        Guard::Query(name.into())
+    }
+
+    pub fn query_eq<S: Into<String>, V: Into<String>>(name: S, value: V) -> Guard {
+        Guard::QueryEq(name.into(), value.into())
+    }
+
+    pub fn header<S: Into<String>>(name: S) -> Guard {
+        Guard::Header(name.into())
+    }
+
+    pub fn header_eq<S: Into<String>, V: Into<String>>(name: S, value: V) -> Guard {
+        Guard::HeaderEq(name.into(), value.into())
+    }
+
+    pub fn content_type<S: Into<String>>(media_type: S) -> Guard {
+        Guard::ContentType(media_type.into())
+    }
+
+    pub fn accept<S: Into<String>>(media_type: S) -> Guard {
+        Guard::Accept(media_type.into())
+    }
+
+    /// Check the guard against the request.
+    pub fn matches(&self, parts: &RequestParts) -> bool {
+        match *self {
+            Guard::Query(ref name) => !parts.query_values(name).is_empty(),
+            Guard::QueryEq(ref name, ref value) => parts.query_values(name).contains(value),
+            Guard::Header(ref name) => parts.headers.and_then(|h| h.get_raw(name)).is_some(),
+            Guard::HeaderEq(ref name, ref value) => parts.header_values(name).contains(&value.as_str()),
+            Guard::ContentType(ref media_type) => parts.header_values("Content-Type").first()
+                .is_some_and(|value| media_matches(media_type, essence(value))),
+            Guard::Accept(ref media_type) => parts.accept_quality(media_type) > 0
+        }
+    }
+}
+
+impl<'a> RequestParts<'a> {
+    /// The quality (in thousandths) with which the `Accept` header of the request allows `media_type`.
+    ///
+    /// A request without `Accept` allows everything.
+    pub fn accept_quality(&self, media_type: &str) -> u32 {
+        let ranges = self.header_values("Accept");
+        if ranges.is_empty() {
+            return 1000;
+        }
+
+        ranges.iter()
+            .filter(|range| media_matches(essence(range), media_type))
+            .map(|range| quality(range))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// How much the request prefers `locale` by `Accept-Language`, in thousandths.
+    ///
+    /// A language range matches a locale if it is equal, a prefix like `de` for `de-AT` or has
+    /// the locale as prefix like `de-AT` for `de`. Without the header every locale has 1000.
+    pub fn language_quality(&self, locale: &str) -> u32 {
+        let ranges = self.header_values("Accept-Language");
+        if ranges.is_empty() {
+            return 1000;
+        }
+
+        ranges.iter()
+            .filter(|range| language_matches(essence(range), locale))
+            .map(|range| quality(range))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The media type without parameters, e.g. `text/html` for `text/html; charset=utf-8`.
+fn essence(value: &str) -> &str {
+    value.split(';').next().unwrap_or("").trim()
+}
+
+/// The `q` parameter of a media range in thousandths.
+fn quality(range: &str) -> u32 {
+    range.split(';').skip(1)
+        .map(|param| param.trim())
+        .find(|param| param.starts_with("q="))
+        .and_then(|param| param[2..].parse::<f32>().ok())
+        .map_or(1000, |q| (q.clamp(0.0, 1.0) * 1000.0) as u32)
+}
+
+/// Check if the language range `range` matches `locale`.
+fn language_matches(range: &str, locale: &str) -> bool {
+    let prefix_of = |a: &str, b: &str| b.len() > a.len() && b[..a.len()].eq_ignore_ascii_case(a) && b[a.len()..].starts_with('-');
+    range == "*" || range.eq_ignore_ascii_case(locale) || prefix_of(range, locale) || prefix_of(locale, range)
+}
+
+/// Check if the media type `value` falls into `range`, which may use `*` for the type or subtype.
+fn media_matches(range: &str, value: &str) -> bool {
+    let mut range_parts = range.splitn(2, '/');
+    let mut value_parts = value.splitn(2, '/');
+
+    for _ in 0..2 {
+        match (range_parts.next(), value_parts.next()) {
+            (Some("*"), Some(_)) => (),
+            (Some(r), Some(v)) if r.eq_ignore_ascii_case(v) => (),
+            _ => return false
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Guard, RequestParts};
+    use iron::headers::Headers;
+
+    #[test]
+    fn test_query_guards() {
+        let parts = RequestParts { query: Some("action=list&tag=a&tag=b"), ..RequestParts::default() };
+        assert!(Guard::query("action").matches(&parts));
+        assert!(Guard::query_eq("action", "list").matches(&parts));
+        assert!(Guard::query_eq("tag", "b").matches(&parts));
+        assert!(!Guard::query_eq("action", "create").matches(&parts));
+        assert!(!Guard::query("page").matches(&RequestParts::default()));
+    }
+
+    #[test]
+    fn test_header_guards() {
+        let mut headers = Headers::new();
+        headers.set_raw("X-Api-Version", vec![b"2".to_vec()]);
+        headers.set_raw("Accept", vec![b"text/html, application/json".to_vec()]);
+        let parts = RequestParts { headers: Some(&headers), ..RequestParts::default() };
+
+        assert!(Guard::header("x-api-version").matches(&parts));
+        assert!(Guard::header_eq("X-Api-Version", "2").matches(&parts));
+        assert!(!Guard::header_eq("X-Api-Version", "1").matches(&parts));
+        assert!(Guard::header_eq("Accept", "application/json").matches(&parts));
+        assert!(!Guard::header("Authorization").matches(&parts));
+    }
+
+    #[test]
+    fn test_media_guards() {
+        let mut headers = Headers::new();
+        headers.set_raw("Content-Type", vec![b"multipart/form-data; boundary=x".to_vec()]);
+        headers.set_raw("Accept", vec![b"text/html, application/*;q=0.5, image/png;q=0".to_vec()]);
+        let parts = RequestParts { headers: Some(&headers), ..RequestParts::default() };
+
+        assert!(Guard::content_type("multipart/form-data").matches(&parts));
+        assert!(Guard::content_type("multipart/*").matches(&parts));
+        assert!(!Guard::content_type("application/json").matches(&parts));
+        assert!(!Guard::content_type("application/json").matches(&RequestParts::default()));
+
+        assert!(Guard::accept("text/html").matches(&parts));
+        assert!(Guard::accept("application/json").matches(&parts));
+        assert!(!Guard::accept("image/png").matches(&parts));
+        assert!(!Guard::accept("text/plain").matches(&parts));
+        assert!(Guard::accept("text/plain").matches(&RequestParts::default()));
+
+        assert_eq!(parts.accept_quality("text/html"), 1000);
+        assert_eq!(parts.accept_quality("application/json"), 500);
+    }
+}
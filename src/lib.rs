@@ -2,9 +2,42 @@ extern crate iron;
 extern crate route_recognizer as recognizer;
 extern crate url;
 extern crate mount;
+extern crate serde;
+extern crate serde_urlencoded;
+extern crate time;
 
+#[cfg(test)]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(test)]
+extern crate proptest;
+
+#[macro_use]
+pub mod typed;
+pub mod matcher;
 pub mod router;
+pub mod builder;
+pub mod reload;
+pub mod static_files;
+pub mod problem;
 pub mod url_for;
+pub mod query;
+pub mod guard;
+pub mod version;
+pub mod locale;
+pub mod sitemap;
+pub mod method_override;
 
-pub use url_for::url_for;
-pub use router::{RouteHandler, Router, RouterError, get_parameter, requested_url};
\ No newline at end of file
+pub use url_for::{url_for, url_for_locale, UrlParams};
+pub use typed::{TypedRoute, RouteRef};
+pub use router::{RouteHandler, Router, RouterError, MatchedRoute, Conflict, get_parameter, requested_url};
+pub use builder::{RouterBuilder, RegistrationError};
+pub use reload::ReloadableRouter;
+pub use static_files::StaticFiles;
+pub use problem::{Problem, Member};
+pub use query::{Query, query, query_all};
+pub use guard::Guard;
+pub use version::{ApiVersion, VersionSource};
+pub use locale::{Locale, LocaleSource};
+pub use sitemap::{SitemapEntry, ChangeFreq};
+pub use method_override::{OverrideSource, OriginalMethod, FormBody};
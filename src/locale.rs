@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use iron::typemap::Key;
+use iron::{Request, Url};
+use super::guard::RequestParts;
+use super::typed::RouteRef;
+use super::url_for::{url_for_locale, UrlParams};
+
+/// Where the router found the locale of a request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LocaleSource {
+    /// A leading path segment like `/de/ueber-uns`.
+    PathPrefix,
+    /// The locale the request prefers by `Accept-Language`.
+    AcceptLanguage,
+    /// The first locale given to `Router::locales`.
+    Default
+}
+
+/// The locale of the current request.
+///
+/// It is inserted into the request extensions by the router when locales are enabled with
+/// `Router::locales`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Locale {
+    pub locale: String,
+    pub source: LocaleSource
+}
+
+impl Key for Locale { type Value = Locale; }
+
+/// The translated globs of routes registered with `Router::localized`, by route id.
+pub struct LocaleMap;
+impl Key for LocaleMap { type Value = HashMap<String, Vec<(String, String)>>; }
+
+/// The locale among `locales` the request prefers by `Accept-Language`.
+///
+/// Returns `None` if the request has no `Accept-Language` or accepts none of the locales.
+pub fn negotiate(parts: &RequestParts, locales: &[String]) -> Option<String> {
+    if parts.header_values("Accept-Language").is_empty() {
+        return None;
+    }
+
+    let mut best: Option<(u32, &String)> = None;
+    for locale in locales {
+        let quality = parts.language_quality(locale);
+        if quality > 0 && best.is_none_or(|(best_quality, _)| quality > best_quality) {
+            best = Some((quality, locale));
+        }
+    }
+    best.map(|(_, locale)| locale.clone())
+}
+
+/// Links to all translations of a route registered with `Router::localized`, with their locales.
+///
+/// They can be rendered as `<link rel="alternate" hreflang="de" href="...">`. The list is empty
+/// for routes which are not localized.
+pub fn alternates<R: RouteRef<P>, P: Into<UrlParams>>(request: &Request, route: R, params: P) -> Vec<(String, Url)> {
+    let params = params.into();
+    let locales: Vec<String> = request.extensions.get::<LocaleMap>()
+        .and_then(|map| map.get(route.route_id()))
+        .map(|translations| translations.iter().map(|(locale, _)| locale.clone()).collect())
+        .unwrap_or_default();
+
+    locales.into_iter()
+        .map(|locale| {
+            let url = url_for_locale(request, route.route_id(), &locale, params.clone());
+            (locale, url)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::negotiate;
+    use guard::RequestParts;
+    use iron::headers::Headers;
+
+    #[test]
+    fn test_negotiate() {
+        let locales = vec!["en".to_string(), "de".to_string(), "pt-BR".to_string()];
+        let negotiated = |accept_language: &str| {
+            let mut headers = Headers::new();
+            headers.set_raw("Accept-Language", vec![accept_language.as_bytes().to_vec()]);
+            negotiate(&RequestParts { headers: Some(&headers), ..RequestParts::default() }, &locales)
+        };
+
+        assert_eq!(negotiated("de-AT, en;q=0.5"), Some("de".to_string()));
+        assert_eq!(negotiated("fr, en;q=0.8, de;q=0.9"), Some("de".to_string()));
+        assert_eq!(negotiated("pt"), Some("pt-BR".to_string()));
+        assert_eq!(negotiated("*"), Some("en".to_string()));
+        assert_eq!(negotiated("fr"), None);
+        assert_eq!(negotiate(&RequestParts::default(), &locales), None);
+    }
+}
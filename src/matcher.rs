@@ -0,0 +1,725 @@
+//! Matching of request paths against route globs.
+//!
+//! A glob is split into segments by `/`:
+//!
+//! * `users` is a static segment, matching only itself,
+//! * `:id` is a parameter, matching any non-empty segment,
+//! * `:id<int>` is a constrained parameter, matching only segments satisfying the `Constraint`,
+//! * `*path` is a catch-all parameter, matching one or more segments including the `/` between them.
+//!
+//! Parts of a glob in parentheses are optional, e.g. `/posts(/page/:page)` matches `/posts` and
+//! `/posts/page/2`. `:q?` is short for `(/:q)`. Parameters of optional parts can have a default,
+//! e.g. `:page=1` or `:page<int>=1`, which they take if the part is left out of the path.
+//! Balanced parentheses within a static segment are literal, e.g. `/wiki/Foo_(bar)`.
+//!
+//! If several globs match a path, the one with the higher explicit priority wins. For equal
+//! priorities the segments are compared from left to right and the first difference decides:
+//! static > constrained parameter > parameter > catch-all. Globs of the same rank and priority
+//! which can match the same path, e.g. `/users/:id<int>` and `/users/:n<alnum>`, are ambiguous,
+//! the one registered first wins. Optional parts don't count for the comparison.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use recognizer::{Match, Params};
+
+/// A condition a parameter value must satisfy, written `:name<constraint>` in a glob.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constraint {
+    /// `<int>`: ASCII digits.
+    Int,
+    /// `<alpha>`: ASCII letters.
+    Alpha,
+    /// `<alnum>`: ASCII letters and digits.
+    Alnum,
+    /// `<uuid>`: a hyphenated UUID.
+    Uuid,
+    /// `<a|b|c>`: one of the listed values.
+    OneOf(Vec<String>)
+}
+
+impl Constraint {
+    fn parse(constraint: &str) -> Option<Constraint> {
+        match constraint {
+            "int" => Some(Constraint::Int),
+            "alpha" => Some(Constraint::Alpha),
+            "alnum" => Some(Constraint::Alnum),
+            "uuid" => Some(Constraint::Uuid),
+            _ if constraint.contains('|') && !constraint.split('|').any(|v| v.is_empty()) =>
+                Some(Constraint::OneOf(constraint.split('|').map(|v| v.to_string()).collect())),
+            _ => None
+        }
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match *self {
+            Constraint::Int => value.bytes().all(|b| b.is_ascii_digit()),
+            Constraint::Alpha => value.bytes().all(|b| b.is_ascii_alphabetic()),
+            Constraint::Alnum => value.bytes().all(|b| b.is_ascii_alphanumeric()),
+            Constraint::Uuid => value.len() == 36 && value.char_indices().all(|(i, c)| match i {
+                8 | 13 | 18 | 23 => c == '-',
+                _ => c.is_ascii_hexdigit()
+            }),
+            Constraint::OneOf(ref values) => values.iter().any(|v| v == value)
+        }
+    }
+
+    /// Whether a value can satisfy both constraints.
+    pub fn overlaps(&self, other: &Constraint) -> bool {
+        match (self, other) {
+            (Constraint::OneOf(values), other) | (other, Constraint::OneOf(values)) =>
+                values.iter().any(|v| other.matches(v)),
+            (Constraint::Int, Constraint::Alpha) | (Constraint::Alpha, Constraint::Int) => false,
+            // A UUID has hyphens, which no other constraint allows.
+            (Constraint::Uuid, other) | (other, Constraint::Uuid) => *other == Constraint::Uuid,
+            _ => true
+        }
+    }
+}
+
+/// Defaults of parameters by name.
+type Defaults = Vec<(String, String)>;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Static(String),
+    Param(String, Option<Constraint>),
+    CatchAll(String),
+    /// Segments which can be left out together, with the defaults of their parameters.
+    Optional(Vec<Segment>, Defaults)
+}
+
+impl Segment {
+    fn rank(&self) -> Option<u8> {
+        match *self {
+            Segment::Static(_) => Some(3),
+            Segment::Param(_, Some(_)) => Some(2),
+            Segment::Param(_, None) => Some(1),
+            Segment::CatchAll(_) => Some(0),
+            Segment::Optional(..) => None
+        }
+    }
+
+    /// Equal apart from the parameter name.
+    fn same_shape(&self, other: &Segment) -> bool {
+        match (self, other) {
+            (Segment::Static(a), Segment::Static(b)) => a == b,
+            (Segment::Param(_, a), Segment::Param(_, b)) => a == b,
+            (Segment::CatchAll(_), Segment::CatchAll(_)) => true,
+            (Segment::Optional(a, _), Segment::Optional(b, _)) =>
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.same_shape(b)),
+            _ => false
+        }
+    }
+
+    /// Both segments, which are not optional parts, can match the same part of a path with the same rank.
+    fn overlaps(&self, other: &Segment) -> bool {
+        match (self, other) {
+            (Segment::Param(_, Some(a)), Segment::Param(_, Some(b))) => a.overlaps(b),
+            _ => self.same_shape(other)
+        }
+    }
+
+    /// The segment for building a path, `None` for optional parts.
+    fn path_segment<'a>(&'a self, default: &dyn Fn(&str) -> Option<&'a str>) -> Option<PathSegment<'a>> {
+        match *self {
+            Segment::Static(ref s) => Some(PathSegment::Static(s)),
+            Segment::Param(ref name, _) => Some(PathSegment::Param(name, default(name), false)),
+            Segment::CatchAll(ref name) => Some(PathSegment::Param(name, default(name), true)),
+            Segment::Optional(..) => None
+        }
+    }
+}
+
+/// The name of the parameter in a glob segment, `id` for `:id`, `:id<int>`, `:id?`, `:id=1` and `*id`.
+pub fn param_name(segment: &str) -> Option<&str> {
+    if segment.len() > 1 && (segment.starts_with(':') || segment.starts_with('*')) {
+        segment[1..].split(|c| "<?=()".contains(c)).next()
+    } else {
+        None
+    }
+}
+
+/// Whether the parentheses in `text` are balanced, so they are literal in a static segment.
+fn balanced(text: &str) -> bool {
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return false,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+/// A segment of a path built from a glob, see `Pattern::path`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment<'a> {
+    Static(&'a str),
+    /// A parameter with its default, `true` for catch-all parameters.
+    Param(&'a str, Option<&'a str>, bool)
+}
+
+/// Parse a single segment, with the default of its parameter and whether it is optional (`:q?`).
+fn parse_segment(segment: &str) -> Result<(Segment, Option<String>, bool), String> {
+    if !segment.starts_with(':') && !segment.starts_with('*') {
+        return Ok((Segment::Static(segment.to_string()), None, false));
+    }
+
+    let (spec, default) = match segment.find('=') {
+        Some(i) => (&segment[..i], Some(segment[i + 1..].to_string())),
+        None => (segment, None)
+    };
+    let (spec, optional) = spec.strip_suffix('?').map_or((spec, false), |spec| (spec, true));
+    let name = match param_name(spec) {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => return Err(format!("parameter without name in {:?}", segment))
+    };
+
+    let parsed = if spec.starts_with('*') {
+        if spec.contains('<') {
+            return Err(format!("catch-all parameter {:?} can't be constrained", segment));
+        }
+        Segment::CatchAll(name)
+    } else if spec.contains('<') {
+        match param_constraint(spec) {
+            Some(constraint) => Segment::Param(name, Some(constraint)),
+            None => return Err(format!("unknown constraint in {:?}", segment))
+        }
+    } else {
+        Segment::Param(name, None)
+    };
+
+    Ok((parsed, default, optional))
+}
+
+/// The constraint of the parameter in a glob segment, if any.
+pub fn param_constraint(segment: &str) -> Option<Constraint> {
+    if !segment.starts_with(':') || !segment.ends_with('>') {
+        return None
+    }
+
+    segment.find('<').and_then(|i| Constraint::parse(&segment[i + 1..segment.len() - 1]))
+}
+
+/// A glob which is invalid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidGlob {
+    pub glob: String,
+    pub reason: String
+}
+
+impl fmt::Display for InvalidGlob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid glob {}: {}", self.glob, self.reason)
+    }
+}
+
+/// A parsed glob.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    glob: String,
+    segments: Vec<Segment>
+}
+
+impl Pattern {
+    pub fn parse(glob: &str) -> Result<Pattern, InvalidGlob> {
+        let invalid = |reason: String| InvalidGlob { glob: glob.to_string(), reason };
+        let normalized = glob.strip_prefix('/').unwrap_or(glob);
+        let mut segments = Vec::new();
+        let mut group: Option<(Vec<Segment>, Defaults)> = None;
+
+        for (i, text) in normalized.split('/').enumerate() {
+            // `(` at the end of a segment opens an optional part, `)` closes one unless it is
+            // part of a literal like `Foo_(bar)`.
+            let (text, opens) = text.strip_suffix('(').map_or((text, false), |text| (text, true));
+            let literal = |text: &str| !text.starts_with(':') && !text.starts_with('*') && balanced(text);
+            let (text, closes) = match text.strip_suffix(')') {
+                Some(inner) if !literal(text) => (inner, true),
+                _ => (text, false)
+            };
+            if text.contains(['(', ')'].as_ref()) && !literal(text) {
+                return Err(invalid(format!("unbalanced parentheses in {:?}", text)));
+            }
+            if closes && group.is_none() {
+                return Err(invalid("`)` without `(`".to_string()));
+            }
+
+            if opens && text.is_empty() && i > 0 {
+                return Err(invalid("`(` has to follow a segment, e.g. `/posts(/page/:page)`".to_string()));
+            }
+
+            // A glob may start with an optional part, e.g. `(/:lang)/posts`.
+            if !(opens && text.is_empty()) {
+                let (segment, default, optional) = parse_segment(text).map_err(&invalid)?;
+                match (group.as_mut(), optional, default) {
+                    (Some(_), _, _) if text.is_empty() => return Err(invalid("empty segment in an optional part".to_string())),
+                    (Some(_), true, _) => return Err(invalid(format!("optional parameter {:?} in an optional part", text))),
+                    (Some((inner, defaults)), false, default) => {
+                        defaults.extend(default.map(|value| (param_name(text).unwrap_or_default().to_string(), value)));
+                        inner.push(segment);
+                    },
+                    (None, true, default) => {
+                        let defaults = default.map(|value| (param_name(text).unwrap_or_default().to_string(), value));
+                        segments.push(Segment::Optional(vec![segment], defaults.into_iter().collect()));
+                    },
+                    (None, false, Some(_)) => return Err(invalid(format!("required parameter {:?} can't have a default", text))),
+                    (None, false, None) => segments.push(segment)
+                }
+            }
+
+            if closes {
+                let (inner, defaults) = group.take().unwrap_or_default();
+                segments.push(Segment::Optional(inner, defaults));
+            }
+            if opens {
+                if group.is_some() {
+                    return Err(invalid("optional parts can't be nested".to_string()));
+                }
+                group = Some((Vec::new(), Vec::new()));
+            }
+        }
+
+        if group.is_some() {
+            return Err(invalid("`(` without `)`".to_string()));
+        }
+        Ok(Pattern { glob: normalized.to_string(), segments })
+    }
+
+    /// The glob without leading slash.
+    pub fn glob(&self) -> &str {
+        &self.glob
+    }
+
+    /// Check if both patterns match the same paths with the same rank.
+    pub fn same_shape(&self, other: &Pattern) -> bool {
+        self.segments.len() == other.segments.len() &&
+            self.segments.iter().zip(other.segments.iter()).all(|(a, b)| a.same_shape(b))
+    }
+
+    /// Check if both patterns can match a path with the same rank, so neither is preferred.
+    ///
+    /// Globs with optional parts are compared in all their forms with and without each part.
+    pub fn overlaps(&self, other: &Pattern) -> bool {
+        if self.rank() != other.rank() {
+            return false;
+        }
+
+        let others = other.expand();
+        self.expand().iter().any(|segments| others.iter().any(|other_segments| {
+            segments.len() == other_segments.len() &&
+                segments.iter().zip(other_segments.iter()).all(|(a, b)| a.overlaps(b))
+        }))
+    }
+
+    /// The segments of every form of the glob, with and without each optional part.
+    fn expand(&self) -> Vec<Vec<&Segment>> {
+        let mut forms = vec![Vec::new()];
+        for segment in &self.segments {
+            match *segment {
+                Segment::Optional(ref inner, _) => {
+                    let with_part: Vec<Vec<&Segment>> = forms.iter()
+                        .map(|form| form.iter().cloned().chain(inner.iter()).collect())
+                        .collect();
+                    forms.extend(with_part);
+                },
+                ref segment => forms.iter_mut().for_each(|form| form.push(segment))
+            }
+        }
+        forms
+    }
+
+    /// The first segment if it is static, paths have to start with it.
+    fn first_static(&self) -> Option<&str> {
+        match self.segments.first() {
+            Some(Segment::Static(s)) => Some(s),
+            _ => None
+        }
+    }
+
+    fn rank(&self) -> Vec<u8> {
+        self.segments.iter().filter_map(|s| s.rank()).collect()
+    }
+
+    /// The names of the parameters outside of optional parts, every path of the glob has them.
+    pub fn required_params(&self) -> Vec<&str> {
+        self.path(|_| false).into_iter()
+            .filter_map(|segment| match segment {
+                PathSegment::Param(name, _, _) => Some(name),
+                PathSegment::Static(_) => None
+            })
+            .collect()
+    }
+
+    /// The segments of a path for this glob.
+    ///
+    /// `include` is called with the parameters of each optional part and their defaults,
+    /// the part is left out if it returns `false`.
+    pub fn path<F: FnMut(&[(&str, Option<&str>)]) -> bool>(&self, mut include: F) -> Vec<PathSegment<'_>> {
+        let mut path = Vec::new();
+        for segment in &self.segments {
+            match *segment {
+                Segment::Optional(ref inner, ref defaults) => {
+                    let default = |name: &str| defaults.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str());
+                    let inner: Vec<PathSegment> = inner.iter().filter_map(|s| s.path_segment(&default)).collect();
+                    let params: Vec<(&str, Option<&str>)> = inner.iter()
+                        .filter_map(|s| match *s {
+                            PathSegment::Param(name, default, _) => Some((name, default)),
+                            PathSegment::Static(_) => None
+                        })
+                        .collect();
+                    if include(&params) {
+                        path.extend(inner);
+                    }
+                },
+                ref segment => path.extend(segment.path_segment(&|_| None))
+            }
+        }
+        path
+    }
+
+    fn matches(&self, path: &str) -> Option<Params> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let parts: Vec<&str> = path.split('/').collect();
+        let mut values = Vec::new();
+
+        if !match_segments(&self.segments, &[], &parts, &mut values) {
+            return None
+        }
+        let mut params = Params::new();
+        for (name, value) in values {
+            params.insert(name.to_string(), value);
+        }
+        Some(params)
+    }
+}
+
+/// Match `parts` against `segments` followed by `after`, adding the parameters to `values`.
+///
+/// `values` is left as it was if they don't match.
+fn match_segments<'s>(segments: &'s [Segment], after: &'s [Segment], parts: &[&str], values: &mut Vec<(&'s str, String)>) -> bool {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None if after.is_empty() => return parts.is_empty(),
+        None => return match_segments(after, &[], parts, values)
+    };
+
+    let len = values.len();
+    let matched = match *segment {
+        Segment::Static(ref s) => !parts.is_empty() && parts[0] == s && match_segments(rest, after, &parts[1..], values),
+        Segment::Param(ref name, ref constraint) => {
+            if parts.is_empty() || parts[0].is_empty() || !constraint.as_ref().is_none_or(|c| c.matches(parts[0])) {
+                return false
+            }
+            values.push((name, parts[0].to_string()));
+            match_segments(rest, after, &parts[1..], values)
+        },
+        // Take as many segments as possible, but leave enough for the rest of the glob.
+        Segment::CatchAll(ref name) => (1..parts.len() + 1).rev().any(|taken| {
+            if (taken == 1 && parts[0].is_empty()) || !match_segments(rest, after, &parts[taken..], values) {
+                return false
+            }
+            values.push((name, parts[..taken].join("/")));
+            true
+        }),
+        // Optional parts aren't nested, so `after` is empty here.
+        Segment::Optional(ref inner, ref defaults) => {
+            if match_segments(inner, rest, parts, values) {
+                true
+            } else if match_segments(rest, after, parts, values) {
+                // The part is left out, its parameters take their defaults.
+                values.extend(defaults.iter().map(|(name, value)| (name.as_str(), value.clone())));
+                true
+            } else {
+                false
+            }
+        }
+    };
+
+    if !matched {
+        values.truncate(len);
+    }
+    matched
+}
+
+#[derive(Clone)]
+struct Entry<T> {
+    pattern: Pattern,
+    rank: Vec<u8>,
+    priority: i32,
+    value: T
+}
+
+/// Maps globs to values and finds the values of the globs matching a path.
+#[derive(Clone)]
+pub struct Matcher<T> {
+    entries: Vec<Entry<T>>,
+    /// The entries by the static first segment of their glob, so only these are tried for paths
+    /// starting with it.
+    by_first: HashMap<String, Vec<usize>>,
+    /// The entries starting with a parameter or an optional part, they are tried for all paths.
+    dynamic: Vec<usize>
+}
+
+impl<T> Default for Matcher<T> {
+    fn default() -> Matcher<T> {
+        Matcher::new()
+    }
+}
+
+impl<T> Matcher<T> {
+    pub fn new() -> Matcher<T> {
+        Matcher { entries: Vec::new(), by_first: HashMap::new(), dynamic: Vec::new() }
+    }
+
+    fn reindex(&mut self) {
+        self.by_first.clear();
+        self.dynamic.clear();
+        for (i, entry) in self.entries.iter().enumerate() {
+            match entry.pattern.first_static() {
+                Some(first) => self.by_first.entry(first.to_string()).or_default().push(i),
+                None => self.dynamic.push(i)
+            }
+        }
+    }
+
+    fn position(&self, glob: &str) -> Option<usize> {
+        let glob = glob.strip_prefix('/').unwrap_or(glob);
+        self.entries.iter().position(|e| e.pattern.glob == glob)
+    }
+
+    /// The value of exactly this glob.
+    pub fn get(&self, glob: &str) -> Option<&T> {
+        self.position(glob).map(|i| &self.entries[i].value)
+    }
+
+    pub fn get_mut(&mut self, glob: &str) -> Option<&mut T> {
+        match self.position(glob) {
+            Some(i) => Some(&mut self.entries[i].value),
+            None => None
+        }
+    }
+
+    /// The value of the glob of `pattern`, inserted with `default` if it is new.
+    pub fn entry<F: FnOnce() -> T>(&mut self, pattern: Pattern, default: F) -> &mut T {
+        let i = match self.position(&pattern.glob) {
+            Some(i) => i,
+            None => {
+                let rank = pattern.rank();
+                self.entries.push(Entry { pattern, rank, priority: 0, value: default() });
+                self.reindex();
+                self.entries.len() - 1
+            }
+        };
+
+        &mut self.entries[i].value
+    }
+
+    pub fn remove(&mut self, glob: &str) -> Option<T> {
+        let entry = self.position(glob).map(|i| self.entries.remove(i))?;
+        self.reindex();
+        Some(entry.value)
+    }
+
+    pub fn priority(&self, glob: &str) -> Option<i32> {
+        self.position(glob).map(|i| self.entries[i].priority)
+    }
+
+    /// Set the explicit priority of a glob, globs with higher priorities are preferred.
+    pub fn set_priority(&mut self, glob: &str, priority: i32) -> bool {
+        match self.position(glob) {
+            Some(i) => {
+                self.entries[i].priority = priority;
+                true
+            },
+            None => false
+        }
+    }
+
+    /// All globs with their priority and value, in registration order.
+    pub fn iter(&self) -> Vec<(&Pattern, i32, &T)> {
+        self.entries.iter().map(|e| (&e.pattern, e.priority, &e.value)).collect()
+    }
+
+    /// The values of all globs matching `path`, best match first.
+    pub fn recognize(&self, path: &str) -> Vec<Match<&T>> {
+        let first = path.strip_prefix('/').unwrap_or(path).split('/').next().unwrap_or_default();
+        let mut candidates: Vec<usize> = self.by_first.get(first).map_or(&[][..], |i| &i[..]).iter()
+            .chain(self.dynamic.iter())
+            .cloned()
+            .collect();
+        candidates.sort_unstable();
+
+        let mut matches: Vec<(&Entry<T>, Params)> = candidates.into_iter()
+            .filter_map(|i| self.entries[i].pattern.matches(path).map(|params| (&self.entries[i], params)))
+            .collect();
+
+        // A stable sort keeps the registration order for ambiguous globs.
+        matches.sort_by(|&(a, _), &(b, _)| compare(b, a));
+        matches.into_iter().map(|(e, params)| Match::new(&e.value, params)).collect()
+    }
+}
+
+fn compare<T>(a: &Entry<T>, b: &Entry<T>) -> Ordering {
+    a.priority.cmp(&b.priority).then_with(|| a.rank.cmp(&b.rank))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Matcher, Pattern, Constraint, param_name};
+
+    fn matcher(globs: &[&'static str]) -> Matcher<&'static str> {
+        let mut matcher = Matcher::new();
+        for glob in globs {
+            *matcher.entry(Pattern::parse(glob).unwrap(), || "") = glob;
+        }
+        matcher
+    }
+
+    fn best(matcher: &Matcher<&'static str>, path: &str) -> Option<&'static str> {
+        matcher.recognize(path).first().map(|m| *m.handler)
+    }
+
+    #[test]
+    fn test_priority_rules() {
+        let m = matcher(&["/users/*rest", "/users/:id", "/users/:id<int>", "/users/new"]);
+        assert_eq!(best(&m, "/users/new"), Some("/users/new"));
+        assert_eq!(best(&m, "/users/12"), Some("/users/:id<int>"));
+        assert_eq!(best(&m, "/users/bob"), Some("/users/:id"));
+        assert_eq!(best(&m, "/users/bob/posts"), Some("/users/*rest"));
+        assert_eq!(m.recognize("/users/new").len(), 3);
+    }
+
+    #[test]
+    fn test_index() {
+        let mut m = matcher(&["/users/:id", "/teams/:id", "/:page", "(/:lang<en|de>)/users/new"]);
+        assert_eq!(best(&m, "/users/1"), Some("/users/:id"));
+        assert_eq!(m.recognize("/users/new").len(), 2);
+        assert_eq!(best(&m, "/de/users/new"), Some("(/:lang<en|de>)/users/new"));
+        assert_eq!(best(&m, "/about"), Some("/:page"));
+
+        assert_eq!(m.remove("/users/:id"), Some("/users/:id"));
+        assert_eq!(best(&m, "/teams/1"), Some("/teams/:id"));
+        assert_eq!(best(&m, "/users/1"), None);
+    }
+
+    #[test]
+    fn test_left_to_right() {
+        let m = matcher(&["/:a/b", "/a/:b"]);
+        assert_eq!(best(&m, "/a/b"), Some("/a/:b"));
+    }
+
+    #[test]
+    fn test_explicit_priority() {
+        let mut m = matcher(&["/users/new", "/users/:id"]);
+        assert!(m.set_priority("/users/:id", 1));
+        assert_eq!(best(&m, "/users/new"), Some("/users/:id"));
+    }
+
+    #[test]
+    fn test_params() {
+        let m = matcher(&["/docs/*path", "/users/:id/posts/:post"]);
+        let matched = m.recognize("/users/1/posts/2");
+        assert_eq!(matched[0].params.find("id"), Some("1"));
+        assert_eq!(matched[0].params.find("post"), Some("2"));
+
+        let matched = m.recognize("docs/guide/intro/");
+        assert_eq!(matched[0].params.find("path"), Some("guide/intro/"));
+    }
+
+    #[test]
+    fn test_slashes() {
+        let m = matcher(&["/", "/post", "/files/*path/raw"]);
+        assert_eq!(best(&m, ""), Some("/"));
+        assert_eq!(best(&m, "/"), Some("/"));
+        assert_eq!(best(&m, "/post"), Some("/post"));
+        assert_eq!(best(&m, "/post/"), None);
+        assert_eq!(best(&m, "/files/a/b/raw"), Some("/files/*path/raw"));
+        assert_eq!(best(&m, "/files/raw"), None);
+    }
+
+    #[test]
+    fn test_constraints() {
+        assert!(Constraint::Int.matches("123"));
+        assert!(!Constraint::Int.matches("12a"));
+        assert!(Constraint::Uuid.matches("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(!Constraint::Uuid.matches("67e55044-10b1-426f-9247"));
+
+        let m = matcher(&["/feed/:format<json|xml>"]);
+        assert_eq!(best(&m, "/feed/xml"), Some("/feed/:format<json|xml>"));
+        assert_eq!(best(&m, "/feed/csv"), None);
+    }
+
+    #[test]
+    fn test_optional() {
+        let m = matcher(&["/posts(/page/:page<int>=1)", "/search/:q?", "(/:lang<en|de>)/about"]);
+        let matched = m.recognize("/posts");
+        assert_eq!(*matched[0].handler, "/posts(/page/:page<int>=1)");
+        assert_eq!(matched[0].params.find("page"), Some("1"));
+        assert_eq!(m.recognize("/posts/page/3")[0].params.find("page"), Some("3"));
+        assert_eq!(best(&m, "/posts/page/x"), None);
+        assert_eq!(best(&m, "/posts/"), None);
+
+        assert_eq!(m.recognize("/search")[0].params.find("q"), None);
+        assert_eq!(m.recognize("/search/rust")[0].params.find("q"), Some("rust"));
+        assert_eq!(m.recognize("/de/about")[0].params.find("lang"), Some("de"));
+        assert_eq!(best(&m, "/about"), Some("(/:lang<en|de>)/about"));
+
+        assert_eq!(Pattern::parse("/users/:id/posts(/page/:page=1)").unwrap().required_params(), vec!["id"]);
+
+        let m = matcher(&["/posts/page/:page", "/posts(/page/:page)"]);
+        assert_eq!(best(&m, "/posts/page/2"), Some("/posts/page/:page"));
+    }
+
+    #[test]
+    fn test_invalid_globs() {
+        assert!(Pattern::parse("/users/:").is_err());
+        assert!(Pattern::parse("/users/:id<float>").is_err());
+        assert!(Pattern::parse("/users/*rest<int>").is_err());
+        assert!(Pattern::parse("/users/:id<a|>").is_err());
+        assert!(Pattern::parse("/posts(/page/:page").is_err());
+        assert!(Pattern::parse("/posts/page/:page)").is_err());
+        assert!(Pattern::parse("/posts(/a(/b))").is_err());
+        assert!(Pattern::parse("/posts/:page=1").is_err());
+        assert!(Pattern::parse("/posts/(/:page)").is_err());
+        assert!(Pattern::parse("/wiki/Foo_(bar").is_err());
+    }
+
+    #[test]
+    fn test_literal_parentheses() {
+        let m = matcher(&["/wiki/Foo_(bar)", "/wiki/(a)(b)(/:page)"]);
+        assert_eq!(best(&m, "/wiki/Foo_(bar)"), Some("/wiki/Foo_(bar)"));
+        assert_eq!(best(&m, "/wiki/Foo_"), None);
+        assert_eq!(best(&m, "/wiki/(a)(b)"), Some("/wiki/(a)(b)(/:page)"));
+        assert_eq!(m.recognize("/wiki/(a)(b)/2")[0].params.find("page"), Some("2"));
+    }
+
+    #[test]
+    fn test_shape() {
+        let a = Pattern::parse("/users/:id").unwrap();
+        assert!(a.same_shape(&Pattern::parse("users/:name").unwrap()));
+        assert!(!a.same_shape(&Pattern::parse("/users/:id<int>").unwrap()));
+        assert!(!a.same_shape(&Pattern::parse("/users/new").unwrap()));
+
+        let int = Pattern::parse("/users/:id<int>").unwrap();
+        assert!(int.overlaps(&Pattern::parse("/users/:n<alnum>").unwrap()));
+        assert!(int.overlaps(&Pattern::parse("/users/:n<1|new>").unwrap()));
+        assert!(!int.overlaps(&Pattern::parse("/users/:n<alpha>").unwrap()));
+        assert!(!int.overlaps(&Pattern::parse("/users/:n<uuid>").unwrap()));
+        assert!(!int.overlaps(&Pattern::parse("/users/:n").unwrap()));
+        assert!(!Pattern::parse("/:f<json|xml>").unwrap().overlaps(&Pattern::parse("/:f<csv|tsv>").unwrap()));
+
+        let posts = Pattern::parse("/posts").unwrap();
+        assert!(posts.overlaps(&Pattern::parse("/posts(/page/:page)").unwrap()));
+        assert!(Pattern::parse("/posts(/page/:page)").unwrap().overlaps(&Pattern::parse("/posts/:q?").unwrap()));
+        assert!(!Pattern::parse("/posts/page/:page").unwrap().overlaps(&Pattern::parse("/posts(/page/:page)").unwrap()));
+        assert_eq!(param_name(":id<int>"), Some("id"));
+        assert_eq!(param_name("*path"), Some("path"));
+        assert_eq!(param_name("users"), None);
+        assert_eq!(param_name(":page=1)"), Some("page"));
+        assert_eq!(param_name(":q?"), Some("q"));
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::Read;
+
+use iron::{headers, method, Request};
+use iron::typemap::Key;
+use url::form_urlencoded;
+use super::guard::RequestParts;
+
+/// Where the router looks for the method of a POST request sent by an HTML form.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OverrideSource {
+    /// A header, usually `X-HTTP-Method-Override`.
+    Header(String),
+    /// A query parameter, e.g. `?_method=DELETE`.
+    Query(String),
+    /// A field of an `application/x-www-form-urlencoded` body, usually `_method`.
+    ///
+    /// The router has to read the body for this, which leaves `req.body` empty. Handlers find the
+    /// body in the `FormBody` extension instead. Bodies above the form limit and bodies without
+    /// `Content-Length` are not read, their method is not overridden by the form.
+    Form(String)
+}
+
+/// The size of form bodies read by default, see `Router::override_form_limit`.
+pub const DEFAULT_FORM_LIMIT: u64 = 64 * 1024;
+
+/// The method the request was sent with before it was overridden.
+pub struct OriginalMethod;
+impl Key for OriginalMethod { type Value = method::Method; }
+
+/// The body of a form request, read by the router to look for an overriding method.
+///
+/// It replaces `req.body`, which is empty once the router read it, so form parsers have to be
+/// given these bytes.
+pub struct FormBody;
+impl Key for FormBody { type Value = Vec<u8>; }
+
+/// Where POST requests may choose their method and which methods they may choose.
+///
+/// Only PUT, PATCH and DELETE are allowed, plus the extension methods given to `allow`, so a form
+/// submission can't become a safe method like GET.
+#[derive(Clone, Debug)]
+pub struct MethodOverride {
+    sources: Vec<OverrideSource>,
+    form_limit: u64,
+    extensions: Vec<method::Method>
+}
+
+impl Default for MethodOverride {
+    fn default() -> MethodOverride {
+        MethodOverride { sources: Vec::new(), form_limit: DEFAULT_FORM_LIMIT, extensions: Vec::new() }
+    }
+}
+
+impl MethodOverride {
+    pub fn new() -> MethodOverride {
+        MethodOverride::default()
+    }
+
+    pub fn source(&mut self, source: OverrideSource) -> &mut MethodOverride {
+        self.sources.push(source);
+        self
+    }
+
+    /// The largest form body in bytes which is read to look for the method.
+    pub fn form_limit(&mut self, limit: u64) -> &mut MethodOverride {
+        self.form_limit = limit;
+        self
+    }
+
+    /// Allow overriding with an extension method, e.g. `PURGE`.
+    pub fn allow(&mut self, method: method::Method) -> &mut MethodOverride {
+        self.extensions.push(method);
+        self
+    }
+
+    fn is_allowed(&self, method: &method::Method) -> bool {
+        matches!(*method, method::Put | method::Patch | method::Delete) || self.extensions.contains(method)
+    }
+}
+
+/// Override the method of a POST request with the first allowed method found in the sources.
+///
+/// The original method is kept in the `OriginalMethod` extension.
+pub fn override_method(req: &mut Request, config: &MethodOverride) {
+    if req.method != method::Post || config.sources.is_empty() {
+        return;
+    }
+
+    let reads_form = config.sources.iter().any(|s| matches!(*s, OverrideSource::Form(_)));
+    let fits = req.headers.get::<headers::ContentLength>().is_some_and(|length| length.0 <= config.form_limit);
+    if reads_form && fits && is_form(&RequestParts::new(req)) && !req.extensions.contains::<FormBody>() {
+        let mut body = Vec::new();
+        if req.body.by_ref().take(config.form_limit).read_to_end(&mut body).is_ok() {
+            req.extensions.insert::<FormBody>(body);
+        }
+    }
+
+    let found = find_override(config, &RequestParts::new(req),
+                              req.extensions.get::<FormBody>().map(|b| &b[..]));
+
+    if let Some(method) = found {
+        let original = ::std::mem::replace(&mut req.method, method);
+        req.extensions.insert::<OriginalMethod>(original);
+    }
+}
+
+fn is_form(parts: &RequestParts) -> bool {
+    parts.header_values("Content-Type").first()
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"))
+}
+
+fn find_override(config: &MethodOverride, parts: &RequestParts, body: Option<&[u8]>) -> Option<method::Method> {
+    config.sources.iter()
+        .filter_map(|source| match *source {
+            OverrideSource::Header(ref name) => parts.header_values(name).first().map(|v| v.to_string()),
+            OverrideSource::Query(ref name) => parts.query_values(name).into_iter().next(),
+            OverrideSource::Form(ref name) => body.and_then(|body| form_urlencoded::parse(body)
+                .find(|(key, _)| *key == name.as_str())
+                .map(|(_, value)| value.into_owned()))
+        })
+        .filter_map(|value| value.to_uppercase().parse().ok())
+        .find(|method| config.is_allowed(method))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_override, MethodOverride, OverrideSource};
+    use guard::RequestParts;
+    use iron::headers::Headers;
+    use iron::method;
+
+    #[test]
+    fn test_find_override() {
+        let mut sources = MethodOverride::new();
+        sources.source(OverrideSource::Header("X-HTTP-Method-Override".to_string()))
+            .source(OverrideSource::Query("_method".to_string()))
+            .source(OverrideSource::Form("_method".to_string()));
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-HTTP-Method-Override", vec![b"PATCH".to_vec()]);
+        let parts = RequestParts { headers: Some(&headers), query: Some("_method=put"), ..RequestParts::default() };
+        assert_eq!(find_override(&sources, &parts, None), Some(method::Patch));
+
+        let parts = RequestParts { query: Some("_method=put"), ..RequestParts::default() };
+        assert_eq!(find_override(&sources, &parts, None), Some(method::Put));
+
+        let body = b"name=x&_method=delete";
+        assert_eq!(find_override(&sources, &RequestParts::default(), Some(&body[..])), Some(method::Delete));
+
+        let parts = RequestParts { query: Some("_method=connect"), ..RequestParts::default() };
+        assert_eq!(find_override(&sources, &parts, None), None);
+        let parts = RequestParts { query: Some("_method=get"), ..RequestParts::default() };
+        assert_eq!(find_override(&sources, &parts, None), None);
+
+        let parts = RequestParts { query: Some("_method=purge"), ..RequestParts::default() };
+        assert_eq!(find_override(&sources, &parts, None), None);
+        sources.allow(method::Extension("PURGE".to_string()));
+        assert_eq!(find_override(&sources, &parts, None), Some(method::Extension("PURGE".to_string())));
+        assert_eq!(find_override(&sources, &RequestParts::default(), None), None);
+    }
+}
@@ -0,0 +1,239 @@
+use iron::{headers, status, Response};
+use iron::modifier::Modifier;
+use super::guard::RequestParts;
+
+/// The formats a `Problem` can be rendered in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Json,
+    Html,
+    Text
+}
+
+/// Media types of the formats, preferred in this order if the request accepts several equally.
+static FORMATS: &[(&str, Format)] = &[
+    ("application/problem+json", Format::Json),
+    ("application/json", Format::Json),
+    ("text/html", Format::Html),
+    ("text/plain", Format::Text)
+];
+
+/// A problem document as described by RFC 7807, rendered as `application/problem+json`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    /// A URI identifying the kind of problem, `about:blank` if the status says it all.
+    pub type_uri: String,
+    pub title: String,
+    pub status: status::Status,
+    pub detail: Option<String>,
+    /// The URL of the request the problem occurred for.
+    pub instance: Option<String>,
+    /// Additional members, e.g. the allowed methods of a `405 Method Not Allowed`.
+    pub extensions: Vec<(String, Member)>
+}
+
+/// The value of an additional member of a `Problem`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Member {
+    String(String),
+    List(Vec<String>)
+}
+
+impl Problem {
+    /// A problem of type `about:blank` titled with the reason phrase of `status`.
+    pub fn new(status: status::Status) -> Problem {
+        Problem {
+            type_uri: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status,
+            detail: None,
+            instance: None,
+            extensions: Vec::new()
+        }
+    }
+
+    pub fn detail<S: Into<String>>(mut self, detail: S) -> Problem {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn instance<S: Into<String>>(mut self, instance: S) -> Problem {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    pub fn extension<S: Into<String>>(mut self, name: S, value: Member) -> Problem {
+        self.extensions.push((name.into(), value));
+        self
+    }
+
+    /// Write the problem into `response` in the format the request prefers:
+    /// `application/problem+json`, falling back to HTML or plain text.
+    ///
+    /// The status and the body of the response are replaced, other headers like `Allow` are kept.
+    pub fn render(&self, request_headers: &headers::Headers, response: &mut Response) {
+        let parts = RequestParts { headers: Some(request_headers), ..RequestParts::default() };
+        let (media_type, body) = match negotiate(&parts) {
+            Format::Json => ("application/problem+json", self.to_json()),
+            Format::Html => ("text/html; charset=utf-8", self.to_html()),
+            Format::Text => ("text/plain; charset=utf-8", self.to_text())
+        };
+
+        response.status = Some(self.status);
+        response.headers.set_raw("Content-Type", vec![media_type.as_bytes().to_vec()]);
+        body.modify(response);
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} {}", self.status.to_u16(), self.title);
+        if let Some(ref detail) = self.detail {
+            text.push_str(&format!("\n\n{}", detail));
+        }
+        if let Some(ref instance) = self.instance {
+            text.push_str(&format!("\n\n{}", instance));
+        }
+        text.push('\n');
+        text
+    }
+
+    pub fn to_html(&self) -> String {
+        let title = html_escape(&format!("{} {}", self.status.to_u16(), self.title));
+        let mut html = format!("<!DOCTYPE html>\n<html>\n<head><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n", title);
+        if let Some(ref detail) = self.detail {
+            html.push_str(&format!("<p>{}</p>\n", html_escape(detail)));
+        }
+        if let Some(ref instance) = self.instance {
+            html.push_str(&format!("<p><code>{}</code></p>\n", html_escape(instance)));
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut members = vec![
+            ("type".to_string(), json_string(&self.type_uri)),
+            ("title".to_string(), json_string(&self.title)),
+            ("status".to_string(), self.status.to_u16().to_string())
+        ];
+        if let Some(ref detail) = self.detail {
+            members.push(("detail".to_string(), json_string(detail)));
+        }
+        if let Some(ref instance) = self.instance {
+            members.push(("instance".to_string(), json_string(instance)));
+        }
+        for (name, value) in &self.extensions {
+            let value = match *value {
+                Member::String(ref s) => json_string(s),
+                Member::List(ref list) => format!("[{}]", list.iter().map(|s| json_string(s)).collect::<Vec<_>>().join(","))
+            };
+            members.push((name.clone(), value));
+        }
+
+        let members: Vec<String> = members.iter()
+            .map(|(name, value)| format!("{}:{}", json_string(name), value))
+            .collect();
+        format!("{{{}}}", members.join(","))
+    }
+}
+
+impl Modifier<Response> for Problem {
+    fn modify(self, res: &mut Response) {
+        res.status = Some(self.status);
+        res.headers.set_raw("Content-Type", vec![b"application/problem+json".to_vec()]);
+        self.to_json().modify(res);
+    }
+}
+
+/// The format the request accepts with the highest quality, JSON if it accepts none.
+fn negotiate(parts: &RequestParts) -> Format {
+    let mut best = (0, Format::Json);
+    for &(media_type, format) in FORMATS {
+        let quality = parts.accept_quality(media_type);
+        if quality > best.0 {
+            best = (quality, format);
+        }
+    }
+    best.1
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Problem, Member};
+    use iron::{headers, status, Response};
+
+    fn render(problem: &Problem, accept: Option<&str>) -> (String, String) {
+        let mut request_headers = headers::Headers::new();
+        if let Some(accept) = accept {
+            request_headers.set_raw("Accept", vec![accept.as_bytes().to_vec()]);
+        }
+
+        let mut response = Response::with((status::Ok, "ignored"));
+        response.headers.set(headers::Allow(vec![]));
+        problem.render(&request_headers, &mut response);
+        assert_eq!(response.status, Some(problem.status));
+        assert!(response.headers.get::<headers::Allow>().is_some());
+
+        let mut body = Vec::new();
+        response.body.unwrap().write_body(&mut body).unwrap();
+        let content_type = String::from_utf8(response.headers.get_raw("Content-Type").unwrap()[0].clone()).unwrap();
+        (content_type, String::from_utf8(body).unwrap())
+    }
+
+    #[test]
+    fn test_to_json() {
+        let problem = Problem::new(status::MethodNotAllowed)
+            .detail("No route for \"DELETE\" /users\n")
+            .instance("http://localhost/users")
+            .extension("allowed", Member::List(vec!["GET".to_string(), "HEAD".to_string()]));
+
+        assert_eq!(problem.to_json(), concat!(
+            r#"{"type":"about:blank","title":"Method Not Allowed","status":405,"#,
+            r#""detail":"No route for \"DELETE\" /users\n","instance":"http://localhost/users","#,
+            r#""allowed":["GET","HEAD"]}"#));
+        assert_eq!(Problem::new(status::NotFound).to_json(), r#"{"type":"about:blank","title":"Not Found","status":404}"#);
+
+        let response = Response::with(Problem::new(status::NotFound));
+        assert_eq!(response.status, Some(status::NotFound));
+        assert_eq!(response.headers.get_raw("Content-Type"), Some(&[b"application/problem+json".to_vec()][..]));
+        assert!(response.headers.get::<headers::ContentLength>().is_some());
+    }
+
+    #[test]
+    fn test_render() {
+        let problem = Problem::new(status::NotFound).detail("No route found for GET /<x>").instance("http://localhost/<x>");
+
+        assert_eq!(render(&problem, None).0, "application/problem+json");
+        assert_eq!(render(&problem, Some("application/json")).0, "application/problem+json");
+        assert_eq!(render(&problem, Some("text/html, application/json;q=0.9")),
+                   ("text/html; charset=utf-8".to_string(), concat!(
+                       "<!DOCTYPE html>\n<html>\n<head><title>404 Not Found</title></head>\n<body>\n<h1>404 Not Found</h1>\n",
+                       "<p>No route found for GET /&lt;x&gt;</p>\n<p><code>http://localhost/&lt;x&gt;</code></p>\n</body>\n</html>\n").to_string()));
+        assert_eq!(render(&problem, Some("text/*")).0, "text/html; charset=utf-8");
+        assert_eq!(render(&problem, Some("text/plain")),
+                   ("text/plain; charset=utf-8".to_string(),
+                    "404 Not Found\n\nNo route found for GET /<x>\n\nhttp://localhost/<x>\n".to_string()));
+        assert_eq!(render(&problem, Some("image/png")).0, "application/problem+json");
+    }
+}
@@ -0,0 +1,112 @@
+use std::ops::Deref;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde_urlencoded;
+use url::form_urlencoded;
+
+use iron::{Request, IronError};
+use super::router::RouterError;
+
+/// Get the first value of the query parameter `name` parsed as `T`.
+///
+/// Returns `None` if the parameter is missing or can not be parsed.
+pub fn query<T: FromStr>(req: &Request, name: &str) -> Option<T> {
+    query_impl(req.url.query().unwrap_or(""), name)
+}
+
+/// Get all values of the query parameter `name` in the order they appear in the query string.
+pub fn query_all(req: &Request, name: &str) -> Vec<String> {
+    query_all_impl(req.url.query().unwrap_or(""), name)
+}
+
+/// The whole query string deserialized into `T` with serde.
+#[derive(Debug, PartialEq)]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> Query<T> {
+    /// Deserialize the query string of the request.
+    ///
+    /// Fails with `RouterError::BadRequest` and status 400 if the query string does not fit `T`.
+    pub fn from_request(req: &Request) -> Result<Query<T>, IronError> {
+        from_query(req.url.query().unwrap_or(""))
+            .map_err(|e| bad_request(&e.to_string()).into())
+    }
+
+    /// Unwrap the deserialized value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+fn from_query<T: DeserializeOwned>(query: &str) -> Result<Query<T>, serde_urlencoded::de::Error> {
+    serde_urlencoded::from_str(query).map(Query)
+}
+
+/// A `BadRequest` for a serde error, naming the field if the error does.
+fn bad_request(reason: &str) -> RouterError {
+    let param = reason.find("field `")
+        .map(|i| &reason[i + 7..])
+        .and_then(|rest| rest.find('`').map(|end| rest[..end].to_string()));
+    RouterError::BadRequest { param, reason: reason.to_string() }
+}
+
+fn query_impl<T: FromStr>(query: &str, name: &str) -> Option<T> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| *key == name)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+fn query_all_impl(query: &str, name: &str) -> Vec<String> {
+    form_urlencoded::parse(query.as_bytes())
+        .filter(|(key, _)| *key == name)
+        .map(|(_, value)| value.into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{query_impl, query_all_impl, from_query, bad_request, Query};
+    use router::RouterError;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Page {
+        page: u32,
+        tag: Option<String>
+    }
+
+    #[test]
+    fn test_query() {
+        assert_eq!(query_impl::<u32>("page=2&tag=a", "page"), Some(2));
+        assert_eq!(query_impl::<String>("page=2&tag=a%20b", "tag"), Some("a b".to_string()));
+        assert_eq!(query_impl::<u32>("page=two", "page"), None);
+        assert_eq!(query_impl::<u32>("", "page"), None);
+    }
+
+    #[test]
+    fn test_query_all() {
+        assert_eq!(query_all_impl("tag=a&page=1&tag=b", "tag"), vec!["a", "b"]);
+        assert!(query_all_impl("page=1", "tag").is_empty());
+    }
+
+    #[test]
+    fn test_query_extractor() {
+        let query: Query<Page> = from_query("page=3&tag=rust").unwrap();
+        assert_eq!(query.0, Page { page: 3, tag: Some("rust".to_string()) });
+        assert_eq!(query.page, 3);
+
+        assert!(from_query::<Page>("tag=rust").is_err());
+        assert!(from_query::<Page>("page=x").is_err());
+
+        let error = bad_request(&from_query::<Page>("tag=rust").err().unwrap().to_string());
+        assert!(matches!(error, RouterError::BadRequest { param: Some(ref param), .. } if param == "page"));
+    }
+}
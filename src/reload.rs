@@ -0,0 +1,83 @@
+use std::sync::{Arc, RwLock};
+
+use iron::{Handler, IronResult, Request, Response};
+use super::router::Router;
+
+/// A router whose routes can be changed while it is serving requests.
+///
+/// Clones share the router, so one clone can be given to iron while another one is kept to
+/// change routes, e.g. for feature rollouts. Requests in flight finish with the routes they
+/// started with, so handlers can change the routes too.
+#[derive(Clone)]
+pub struct ReloadableRouter {
+    router: Arc<RwLock<Arc<Router>>>
+}
+
+impl ReloadableRouter {
+    pub fn new(router: Router) -> ReloadableRouter {
+        ReloadableRouter { router: Arc::new(RwLock::new(Arc::new(router))) }
+    }
+
+    /// The router serving new requests, the lock is only held to clone it.
+    fn current(&self) -> Arc<Router> {
+        self.router.read().expect("Router lock poisoned").clone()
+    }
+
+    /// Change the routes, e.g. with `Router::remove` or `Router::replace`.
+    ///
+    /// The router is copied if requests in flight still use it, updates don't wait for them.
+    pub fn update<F: FnOnce(&mut Router)>(&self, update: F) {
+        let mut router = self.router.write().expect("Router lock poisoned");
+        update(Arc::make_mut(&mut router));
+    }
+
+    /// Serve all following requests with `router`.
+    pub fn reload(&self, router: Router) {
+        *self.router.write().expect("Router lock poisoned") = Arc::new(router);
+    }
+
+    /// Read the current routes, e.g. with `Router::routes`.
+    pub fn with<T, F: FnOnce(&Router) -> T>(&self, read: F) -> T {
+        read(&self.current())
+    }
+}
+
+impl Handler for ReloadableRouter {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        self.current().handle(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReloadableRouter;
+    use router::Router;
+
+    #[test]
+    fn test_reload() {
+        let mut router = Router::new();
+        router.get("/users/:id", "users.show").get("/teams", "teams.index");
+
+        let reloadable = ReloadableRouter::new(router);
+        let shared = reloadable.clone();
+        shared.update(|router| {
+            router.remove("teams.index");
+            router.replace("users.show", "/people/:id");
+        });
+        assert_eq!(reloadable.with(|router| router.routes().len()), 1);
+        assert_eq!(reloadable.with(|router| router.glob("users.show").map(|g| g.to_string())), Some("/people/:id".to_string()));
+
+        let mut router = Router::new();
+        router.get("/", "index");
+        shared.reload(router);
+        assert!(reloadable.with(|router| router.glob("index").is_some()));
+
+        // Like a handler changing the routes while its request is in flight.
+        let routes = reloadable.with(|router| {
+            shared.update(|router| { router.get("/admin", "admin"); });
+            router.routes().len()
+        });
+        assert_eq!(routes, 1);
+        assert_eq!(reloadable.with(|router| router.routes().len()), 2);
+    }
+}
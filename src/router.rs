@@ -1,60 +1,729 @@
+use std::io::{self, Write};
+use std::panic;
 use std::sync::Arc;
 use std::collections::{HashMap};
 use iron::{method, Handler, IronResult, Response, Request, status, IronError, Url, headers};
 use iron::modifiers::Redirect;
-use recognizer::Router as Recognizer;
+use iron::response::WriteBody;
 use std::error::Error;
 use std::fmt;
 use iron::typemap::Key;
 use recognizer::{Match, Params};
 use mount;
+use super::guard::{Guard, RequestParts};
+use super::version::{ApiVersion, VersionResolver, VersionSource};
+use super::method_override::{MethodOverride, OverrideSource, override_method};
+use super::typed::TypedRoute;
+use super::matcher::{Matcher, Pattern, PathSegment, param_name};
+use super::url_for::{url_for, url_for_locale, UrlParams};
+use super::static_files::StaticFiles;
+use super::problem::{Problem, Member};
+use super::locale::{self, Locale, LocaleSource, LocaleMap};
+use super::sitemap::{self, SitemapEntry, SitemapUrl};
+use super::query::query;
 
 pub trait RouteHandler: Send + Sync + 'static {
-    fn handle(&self, &mut Request, &str) -> IronResult<Response>;
+    fn handle(&self, req: &mut Request, route_id: &str) -> IronResult<Response>;
 }
 
+#[derive(Clone)]
+struct Route {
+    id: String,
+    guards: Vec<Guard>,
+    version: Option<u32>,
+    /// The locale of a translation registered with `Router::localized`.
+    locale: Option<String>
+}
+
+impl Route {
+    /// Check the version and guards of the route against the request.
+    ///
+    /// Fails with `UnsupportedMediaType` or `NotAcceptable` if only media type guards failed.
+    fn check(&self, parts: &RequestParts) -> Result<(), Rejection> {
+        match (self.version, parts.version) {
+            (Some(version), Some(requested)) if version > requested => return Err(Rejection::NotFound),
+            _ => ()
+        }
+
+        let mut error = None;
+        for guard in self.guards.iter().filter(|guard| !guard.matches(parts)) {
+            match *guard {
+                Guard::ContentType(_) => error = Some(Rejection::UnsupportedMediaType),
+                Guard::Accept(_) => if error.is_none() {
+                    error = Some(Rejection::NotAcceptable)
+                },
+                _ => return Err(Rejection::NotFound)
+            }
+        }
+
+        error.map_or(Ok(()), Err)
+    }
+
+    /// How much the request prefers the media types the route responds with.
+    fn quality(&self, parts: &RequestParts) -> u32 {
+        self.guards.iter()
+            .filter_map(|guard| match *guard {
+                Guard::Accept(ref media_type) => Some(parts.accept_quality(media_type)),
+                _ => None
+            })
+            .min()
+            .unwrap_or(1000)
+    }
+}
+
+/// Why no route was recognized for a request, the router turns it into a `RouterError` with context.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rejection {
+    NotFound,
+    MethodNotAllowed,
+    UnsupportedMediaType,
+    NotAcceptable
+}
+
+impl Rejection {
+    /// Keep the more specific of two rejections.
+    fn or(self, other: Rejection) -> Rejection {
+        match self {
+            Rejection::NotFound | Rejection::MethodNotAllowed => other,
+            _ => self
+        }
+    }
+}
+
+/// Guard count, version and accept quality of a matching route.
+type Rank = (usize, Option<u32>, u32);
+
+/// Pick the route for the request among the candidates of one glob and method.
+///
+/// Among matching routes the most specific one wins, then the highest version, then the one
+/// with the media type the request prefers.
+fn select<'a>(routes: &'a [Route], parts: &RequestParts) -> Result<&'a Route, Rejection> {
+    let mut best: Option<(&Route, Rank)> = None;
+    let mut error = Rejection::NotFound;
+
+    for route in routes {
+        match route.check(parts) {
+            Ok(()) => {
+                let rank = (route.guards.len(), route.version, route.quality(parts));
+                if best.as_ref().is_none_or(|(_, best_rank)| rank > *best_rank) {
+                    best = Some((route, rank));
+                }
+            },
+            Err(Rejection::UnsupportedMediaType) => error = Rejection::UnsupportedMediaType,
+            Err(Rejection::NotAcceptable) if error == Rejection::NotFound => error = Rejection::NotAcceptable,
+            Err(_) => ()
+        }
+    }
+
+    best.map(|(route, _)| route).ok_or(error)
+}
+
+/// Add a route to the candidates of one glob and method.
+///
+/// Routes with more guards are tried first, then routes of higher versions, so a request
+/// falls back to the nearest lower version of a route.
+/// A route of the same version with the same id is replaced, a route with the same guards
+/// is kept after the existing one and reported by `Router::conflicts`.
+fn add_route(routes: &mut Vec<Route>, route: Route) {
+    routes.retain(|r| r.version != route.version || r.id != route.id);
+    routes.push(route);
+    routes.sort_by_key(|r| ::std::cmp::Reverse((r.guards.len(), r.version)));
+}
+
+fn parse_glob(glob: &str) -> Pattern {
+    Pattern::parse(glob).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Pairs of routes which can never be told apart, the first one of each pair wins.
+fn ambiguous<'a>(routes: &'a [Route], others: &'a [Route]) -> Vec<(&'a Route, &'a Route)> {
+    let mut pairs = Vec::new();
+    for route in routes {
+        for other in others {
+            if route.id != other.id && route.guards == other.guards && route.version == other.version {
+                pairs.push((route, other));
+            }
+        }
+    }
+    pairs
+}
+
+/// The routes of a glob by method, `None` for `any` routes.
+type Methods = HashMap<Option<method::Method>, Vec<Route>>;
+
+/// Two routes which can match the same requests, the `shadowed` one is never used for them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    /// The method of both routes, `None` for `any` routes.
+    pub method: Option<method::Method>,
+    pub glob: String,
+    pub route_id: String,
+    pub shadowed_glob: String,
+    pub shadowed_route_id: String
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let method = self.method.as_ref().map_or("ANY".to_string(), |m| m.to_string());
+        write!(f, "{} {} ({}) is shadowed by {} ({})",
+               method, self.shadowed_glob, self.shadowed_route_id, self.glob, self.route_id)
+    }
+}
+
+/// Routes requests to the `RouteHandler`s linked to it.
+///
+/// If several globs match a request, static segments are preferred over constrained parameters,
+/// over parameters, over catch-all parameters, see the `matcher` module. `Router::priority`
+/// overrides these rules, `Router::conflicts` reports routes which can't be told apart.
+/// A route registered for the method of the request wins over an `any` route of the same glob.
+/// Of several routes with the same glob, method and guards the one registered first wins.
+#[derive(Clone)]
 pub struct Router {
-    matcher: Arc<Recognizer<HashMap<method::Method, String>>>,
-    handlers: Vec<Box<RouteHandler + Send + Sync>>,
-    wildcard: Recognizer<String>,
+    matcher: Arc<Matcher<Methods>>,
+    handlers: Vec<Arc<dyn RouteHandler + Send + Sync>>,
     route_ids: HashMap<String, String>,
+    versions: VersionResolver,
+    method_override: MethodOverride,
+    nested: Vec<(String, Router)>,
+    /// Target route id and status of redirect routes, by route id.
+    redirects: HashMap<String, (String, status::Status)>,
+    /// Globs a route is served under besides its own, by route id.
+    aliases: HashMap<String, Vec<String>>,
+    /// Directories and the parameter holding the file path of static file routes, by route id.
+    static_files: HashMap<String, (StaticFiles, String)>,
+    /// Supported locales, the first one is the default.
+    locales: Vec<String>,
+    /// Locales and globs of localized routes, by route id.
+    translations: HashMap<String, Vec<(String, String)>>,
+    /// Routes listed in the sitemap, by route id.
+    sitemap: HashMap<String, SitemapEntry>,
+    /// The route id of the sitemap route, if it is served.
+    sitemap_route: Option<String>,
+    catch_panics: bool,
+    panic_hook: Option<PanicHook>,
+    problem_details: bool
+}
+
+/// Called with the route id and the panic message when a handler panicked.
+pub type PanicHook = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
 }
 
 impl Router {
     pub fn new() -> Router {
         Router {
-            matcher: Arc::new(Recognizer::new()),
+            matcher: Arc::new(Matcher::new()),
             handlers: Vec::new(),
-            wildcard: Recognizer::new(),
-            route_ids: HashMap::new()
+            route_ids: HashMap::new(),
+            versions: VersionResolver::new(),
+            method_override: MethodOverride::new(),
+            nested: Vec::new(),
+            redirects: HashMap::new(),
+            aliases: HashMap::new(),
+            static_files: HashMap::new(),
+            locales: Vec::new(),
+            translations: HashMap::new(),
+            sitemap: HashMap::new(),
+            sitemap_route: None,
+            catch_panics: false,
+            panic_hook: None,
+            problem_details: false
         }
     }
 
-    fn mut_matcher(&mut self) -> &mut Recognizer<HashMap<method::Method, String>> {
-        Arc::get_mut(&mut self.matcher).expect("Cannot modify router at this point.")
+    fn mut_matcher(&mut self) -> &mut Matcher<Methods> {
+        Arc::make_mut(&mut self.matcher)
     }
 
     pub fn link<T: RouteHandler>(&mut self, handler: T) -> &mut Router {
-        self.handlers.push(Box::new(handler));
+        self.handlers.push(Arc::new(handler));
         self
     }
 
     pub fn route<S: AsRef<str>>(&mut self, method: method::Method, glob: S, route_id: &str) -> &mut Router {
+        self.route_when(method, glob, route_id, Vec::new())
+    }
+
+    /// Register a route declared with `typed_route!`.
+    ///
+    /// Panics if the glob has parameters which are not in the parameter type of the route.
+    pub fn typed<P>(&mut self, method: method::Method, route: &TypedRoute<P>) -> &mut Router {
+        let missing = route.missing_params();
+        if !missing.is_empty() {
+            panic!("Route {} has no fields for the parameters {:?}", route.id(), missing);
+        }
 
-        let mut hash: HashMap<method::Method, String>;
+        self.route(method, route.glob(), route.id())
+    }
 
-        if let Some(s) = self.mut_matcher().recognize(glob.as_ref()).ok() {
-            hash = s.handler.clone();
-        } else {
-            hash = HashMap::new();
+    /// Register a route that only matches if all `guards` are satisfied by the request.
+    ///
+    /// Several routes can be registered for the same glob and method this way,
+    /// e.g. `/api?action=list` and `/api?action=create`.
+    pub fn route_when<S: AsRef<str>>(&mut self, method: method::Method, glob: S, route_id: &str, guards: Vec<Guard>) -> &mut Router {
+        self.add(Some(method), glob.as_ref(), Route { id: route_id.to_string(), guards, version: None, locale: None })
+    }
+
+    /// Register a route for API `version` and all later versions which don't register it again.
+    ///
+    /// The versions of a route share its route id and glob, the handler can tell them apart with
+    /// `MatchedRoute::version`.
+    pub fn versioned<S: AsRef<str>>(&mut self, version: u32, method: method::Method, glob: S, route_id: &str) -> &mut Router {
+        self.add(Some(method), glob.as_ref(), Route { id: route_id.to_string(), guards: Vec::new(), version: Some(version), locale: None })
+    }
+
+    /// Add a place the API version of a request is read from, sources are tried in order.
+    pub fn version_source(&mut self, source: VersionSource) -> &mut Router {
+        self.versions.source(source);
+        self
+    }
+
+    /// The API version of requests without a version.
+    pub fn default_version(&mut self, version: u32) -> &mut Router {
+        self.versions.default_version(version);
+        self
+    }
+
+    /// Let POST requests choose the method they are routed with, e.g. for HTML forms.
+    ///
+    /// Sources are tried in order, the original method is kept in the `OriginalMethod` extension.
+    /// Only PUT, PATCH and DELETE can be chosen, see `Router::allow_override` for extension methods.
+    pub fn method_override(&mut self, source: OverrideSource) -> &mut Router {
+        self.method_override.source(source);
+        self
+    }
+
+    /// The largest form body in bytes the router reads for `OverrideSource::Form`, 64 KiB by default.
+    pub fn override_form_limit(&mut self, limit: u64) -> &mut Router {
+        self.method_override.form_limit(limit);
+        self
+    }
+
+    /// Let POST requests choose the extension method `method` too, besides PUT, PATCH and DELETE.
+    ///
+    /// Panics if `method` is not a valid method name.
+    pub fn allow_override(&mut self, method: &str) -> &mut Router {
+        let method = method.parse().unwrap_or_else(|_| panic!("Invalid method: {}", method));
+        self.method_override.allow(method);
+        self
+    }
+
+    /// Enable locales, e.g. `&["en", "de"]`, the first one is the default locale.
+    ///
+    /// The router inserts the `Locale` of each request into its extensions, found in the path prefix,
+    /// in `Accept-Language` or the default. Requests to a path without locale prefix are redirected to
+    /// the path in the locale `Accept-Language` prefers, if the router has it.
+    pub fn locales(&mut self, locales: &[&str]) -> &mut Router {
+        self.locales = locales.iter().map(|locale| locale.to_string()).collect();
+        self
+    }
+
+    /// Register a route with a glob per locale, e.g. `&[("en", "/about"), ("de", "/ueber-uns")]`.
+    ///
+    /// Each translation is served under its locale prefix, `/en/about` and `/de/ueber-uns`, with the
+    /// same route id. `url_for` links to the translation of the current locale, `url_for_locale` to
+    /// the one of another locale and `locale::alternates` to all of them.
+    /// Panics if a locale is not one of `Router::locales`.
+    pub fn localized(&mut self, method: method::Method, route_id: &str, translations: &[(&str, &str)]) -> &mut Router {
+        let mut globs = Vec::new();
+        for &(locale, glob) in translations {
+            if !self.locales.iter().any(|l| l == locale) {
+                panic!("Unknown locale {} for route {}", locale, route_id);
+            }
+            let glob = match glob.trim_start_matches('/') {
+                "" => format!("/{}", locale),
+                glob => format!("/{}/{}", locale, glob)
+            };
+            globs.push((locale.to_string(), glob));
+        }
+
+        for (i, (locale, glob)) in globs.iter().enumerate() {
+            let route = Route { id: route_id.to_string(), guards: Vec::new(), version: None, locale: Some(locale.clone()) };
+            if i == 0 {
+                self.add(Some(method.clone()), glob, route);
+            } else {
+                // Like aliases, the other translations don't change the glob of the route id.
+                add_route(self.mut_matcher().entry(parse_glob(glob), HashMap::new).entry(Some(method.clone())).or_default(), route);
+                let aliases = self.aliases.entry(route_id.to_string()).or_default();
+                if !aliases.contains(glob) {
+                    aliases.push(glob.clone());
+                }
+            }
+        }
+
+        self.translations.insert(route_id.to_string(), globs);
+        self
+    }
+
+    /// List the pages of `route_id` in the sitemap served with `Router::serve_sitemap`.
+    ///
+    /// Routes with parameters list a page for each parameters of `SitemapEntry::params`,
+    /// localized routes list their pages in all locales.
+    /// Panics if there is no such route or if it has parameters without default and no params provider.
+    pub fn sitemap(&mut self, route_id: &str, entry: SitemapEntry) -> &mut Router {
+        let glob = self.route_ids.get(route_id).cloned()
+            .unwrap_or_else(|| panic!("No route with that ID: {}", route_id));
+        if !parse_glob(&glob).required_params().is_empty() && entry.params.is_none() {
+            panic!("Route {} has parameters, list its pages with SitemapEntry::params", route_id);
+        }
+
+        self.sitemap.insert(route_id.to_string(), entry);
+        self
+    }
+
+    /// Serve the sitemap for GET requests to `glob`, e.g. `/sitemap.xml`, with the route id `sitemap:{glob}`.
+    ///
+    /// The URLs are generated with `url_for`. Above `sitemap::MAX_URLS` URLs a sitemap index is
+    /// served, linking to the parts at `?page=1`, `?page=2` and so on.
+    pub fn serve_sitemap<S: AsRef<str>>(&mut self, glob: S) -> &mut Router {
+        let route_id = format!("sitemap:{}", glob.as_ref());
+        self.get(glob.as_ref(), &route_id);
+        self.sitemap_route = Some(route_id);
+        self
+    }
+
+    /// Answer requests whose handler panicked with 500 and a `RouterError::HandlerPanicked`
+    /// instead of letting the panic kill the worker thread.
+    pub fn catch_panics(&mut self) -> &mut Router {
+        self.catch_panics = true;
+        self
+    }
+
+    /// Catch panics of handlers like `catch_panics` and report them to `hook`, e.g. for logging.
+    pub fn on_panic<F: Fn(&str, &str) + Send + Sync + 'static>(&mut self, hook: F) -> &mut Router {
+        self.catch_panics = true;
+        self.panic_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Render `RouterError`s, of the router and of handlers, as problem documents (RFC 7807).
+    ///
+    /// The body is `application/problem+json`, or HTML or plain text if the request prefers them,
+    /// see `Problem::render`. The instance of the problem is the requested URL.
+    /// Only the router given to iron renders errors, nested routers leave it to it.
+    pub fn problem_details(&mut self) -> &mut Router {
+        self.problem_details = true;
+        self
+    }
+
+    /// Mount `router` under the path `prefix`, e.g. `/api`.
+    ///
+    /// Requests below the prefix are handled by the nested router with the prefix stripped,
+    /// unless only a route of this router like `/api/health` matches them.
+    /// Its route ids are also known to this router, so `url_for` can link to them from both routers.
+    pub fn nest<S: AsRef<str>>(&mut self, prefix: S, router: Router) -> &mut Router {
+        let prefix = prefix.as_ref().trim_matches('/').to_string();
+
+        for (id, glob) in &router.route_ids {
+            let glob = format!("/{}/{}", prefix, glob.trim_start_matches('/'));
+            self.route_id(id, &glob);
+        }
+
+        self.nested.push((prefix, router));
+        // Longer prefixes first, so `/api/admin` wins over `/api`.
+        self.nested.sort_by_key(|(prefix, _)| ::std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Prefer the glob of `route_id` over other globs matching the same path.
+    ///
+    /// Globs have priority 0 by default, a higher priority wins over the rules of the `matcher` module.
+    pub fn priority(&mut self, route_id: &str, priority: i32) -> &mut Router {
+        let glob = self.route_ids.get(route_id).cloned()
+            .unwrap_or_else(|| panic!("No route with that ID: {}", route_id));
+
+        if !self.mut_matcher().set_priority(&glob, priority) {
+            panic!("Route {} belongs to a nested router", route_id);
+        }
+        self
+    }
+
+    /// Routes which can't be told apart: the same method and guards on globs of the same rank
+    /// and priority which can match the same path, e.g. `/users/:id` and `/users/:name` or
+    /// `/users/:id<int>` and `/users/:n<alnum>`, or on the same glob.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        let entries = self.matcher.iter();
+
+        for (i, &(pattern, priority, methods)) in entries.iter().enumerate() {
+            for &(other_pattern, other_priority, other_methods) in &entries[i..] {
+                let same_glob = pattern.glob() == other_pattern.glob();
+                if !same_glob && (priority != other_priority || !pattern.overlaps(other_pattern)) {
+                    continue;
+                }
+
+                for (method, routes) in methods {
+                    let others = match other_methods.get(method) {
+                        Some(others) => others,
+                        None => continue
+                    };
+                    for (route, other) in ambiguous(routes, others) {
+                        if same_glob && routes.iter().position(|r| r.id == other.id) < routes.iter().position(|r| r.id == route.id) {
+                            continue;
+                        }
+                        conflicts.push(self.conflict(method.clone(), route, other));
+                    }
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    fn conflict(&self, method: Option<method::Method>, route: &Route, shadowed: &Route) -> Conflict {
+        Conflict {
+            method,
+            glob: self.route_ids.get(&route.id).cloned().unwrap_or_default(),
+            route_id: route.id.clone(),
+            shadowed_glob: self.route_ids.get(&shadowed.id).cloned().unwrap_or_default(),
+            shadowed_route_id: shadowed.id.clone()
         }
+    }
+
+    /// Add a route for `method`, or for any method if it is `None`.
+    fn add(&mut self, method: Option<method::Method>, glob: &str, route: Route) -> &mut Router {
+        let route_id = route.id.clone();
+        let pattern = parse_glob(glob);
+        let methods = self.mut_matcher().entry(pattern, HashMap::new);
 
-        hash.insert(method, route_id.to_string());
-        self.mut_matcher().add(glob.as_ref(), hash);
-        self.route_id(route_id.as_ref(), glob.as_ref());
+        add_route(methods.entry(method).or_default(), route);
+        self.route_id(&route_id, glob);
         self
     }
 
+    /// Unregister all routes of `route_id`, including `any` routes and routes of nested routers.
+    ///
+    /// Returns `false` if there was no such route.
+    pub fn remove(&mut self, route_id: &str) -> bool {
+        let glob = match self.route_ids.remove(route_id) {
+            Some(glob) => glob,
+            None => return false
+        };
+
+        self.take_routes(&glob, route_id, None);
+        for alias in self.aliases.remove(route_id).unwrap_or_default() {
+            self.take_routes(&alias, route_id, None);
+        }
+        self.redirects.remove(route_id);
+        self.static_files.remove(route_id);
+        self.translations.remove(route_id);
+        self.sitemap.remove(route_id);
+        if self.sitemap_route.as_ref().is_some_and(|id| id == route_id) {
+            self.sitemap_route = None;
+        }
+        for (_, router) in &mut self.nested {
+            router.remove(route_id);
+        }
+        true
+    }
+
+    /// Unregister the route of `route_id` for `method` only, the route id stays registered for other methods.
+    ///
+    /// Returns `false` if there was no such route.
+    pub fn remove_method(&mut self, route_id: &str, method: &method::Method) -> bool {
+        let glob = match self.route_ids.get(route_id) {
+            Some(glob) => glob.clone(),
+            None => return false
+        };
+
+        let mut removed = !self.take_routes(&glob, route_id, Some(method)).is_empty();
+        for alias in self.aliases.get(route_id).cloned().unwrap_or_default() {
+            self.take_routes(&alias, route_id, Some(method));
+        }
+        for (_, router) in &mut self.nested {
+            removed |= router.remove_method(route_id, method);
+        }
+
+        if !self.has_routes(&glob, route_id) {
+            self.route_ids.remove(route_id);
+            self.aliases.remove(route_id);
+        }
+        removed
+    }
+
+    /// Move all routes of `route_id` to `glob`, keeping their methods, guards and versions.
+    ///
+    /// The priority of the old glob is not carried over.
+    /// Panics if there is no such route, if it belongs to a nested router, if it is localized or
+    /// if `glob` is invalid. Localized routes are changed by removing them and registering them again.
+    pub fn replace<S: AsRef<str>>(&mut self, route_id: &str, glob: S) -> &mut Router {
+        parse_glob(glob.as_ref());
+        let old_glob = self.route_ids.get(route_id).cloned()
+            .unwrap_or_else(|| panic!("No route with that ID: {}", route_id));
+        if self.nested.iter().any(|(_, router)| router.route_ids.contains_key(route_id)) {
+            panic!("Route {} belongs to a nested router", route_id);
+        }
+        if self.translations.contains_key(route_id) {
+            panic!("Route {} is localized", route_id);
+        }
+
+        let routes = self.take_routes(&old_glob, route_id, None);
+        self.route_ids.remove(route_id);
+
+        for (method, route) in routes {
+            self.add(method, glob.as_ref(), route);
+        }
+        self
+    }
+
+    /// Whether `replace` can move the route `route_id`, a registered route which is neither nested nor localized.
+    pub fn can_replace(&self, route_id: &str) -> bool {
+        self.route_ids.contains_key(route_id) && !self.translations.contains_key(route_id) && !self.is_nested(route_id)
+    }
+
+    /// Whether the route `route_id` belongs to a nested router.
+    pub fn is_nested(&self, route_id: &str) -> bool {
+        self.nested.iter().any(|(_, router)| router.route_ids.contains_key(route_id))
+    }
+
+    /// Redirect requests to `glob` to the route `route_id` with `status`, e.g. `status::MovedPermanently`.
+    ///
+    /// The parameters of `glob` are passed on to `route_id` by name like with `url_for`, parameters
+    /// the target glob doesn't have become query parameters. The query of the request is kept.
+    /// Parameters aren't renamed, so `glob` has to use the names of the target glob, e.g.
+    /// `/old/:id` for a route `/users/:id`, not `/old/:user_id`.
+    /// The redirect is registered for any method with the route id `redirect:{glob}`.
+    /// Panics if there is no route `route_id` yet, if `glob` lacks parameters its glob requires or
+    /// if a catch-all parameter of `glob` is a `:param` of its glob, which can't hold a `/`.
+    pub fn redirect<S: AsRef<str>>(&mut self, glob: S, route_id: &str, status: status::Status) -> &mut Router {
+        let missing = self.missing_redirect_params(glob.as_ref(), route_id)
+            .unwrap_or_else(|| panic!("No route with that ID: {}", route_id));
+        if !missing.is_empty() {
+            panic!("Redirect {} has no values for the parameters {:?} of route {}", glob.as_ref(), missing, route_id);
+        }
+        let catch_all = self.catch_all_redirect_params(glob.as_ref(), route_id);
+        if !catch_all.is_empty() {
+            panic!("Redirect {} passes the catch-all parameters {:?} to single segments of route {}",
+                   glob.as_ref(), catch_all, route_id);
+        }
+
+        let redirect_id = format!("redirect:{}", glob.as_ref());
+        self.any(glob.as_ref(), &redirect_id);
+        self.redirects.insert(redirect_id, (route_id.to_string(), status));
+        self
+    }
+
+    /// The parameters the glob of `route_id` requires which a redirect from `glob` can't pass on,
+    /// `None` if there is no such route.
+    pub fn missing_redirect_params(&self, glob: &str, route_id: &str) -> Option<Vec<String>> {
+        let target = parse_glob(self.route_ids.get(route_id)?);
+        let source = parse_glob(glob);
+        let available = source.required_params();
+        Some(target.required_params().into_iter()
+            .filter(|param| !available.contains(param))
+            .map(|param| param.to_string())
+            .collect())
+    }
+
+    /// The catch-all parameters of `glob` which are `:param`s in the glob of `route_id`, so a
+    /// redirect from `glob` can't pass on values with a `/`.
+    pub fn catch_all_redirect_params(&self, glob: &str, route_id: &str) -> Vec<String> {
+        let target = match self.route_ids.get(route_id) {
+            Some(target) => parse_glob(target),
+            None => return Vec::new()
+        };
+        let single: Vec<&str> = target.path(|_| true).into_iter()
+            .filter_map(|segment| match segment {
+                PathSegment::Param(name, _, false) => Some(name),
+                _ => None
+            })
+            .collect();
+
+        parse_glob(glob).path(|_| true).into_iter()
+            .filter_map(|segment| match segment {
+                PathSegment::Param(name, _, true) if single.contains(&name) => Some(name.to_string()),
+                _ => None
+            })
+            .collect()
+    }
+
+    /// Serve the files of a directory for GET and HEAD requests to `glob`, e.g. `/assets/*path`.
+    ///
+    /// The last segment of `glob` has to be a parameter, it holds the path of the file in the directory.
+    /// The route id is `static:{glob}`, e.g. for `url_for(req, "static:/assets/*path", params)`.
+    pub fn static_files<S: AsRef<str>, F: Into<StaticFiles>>(&mut self, glob: S, files: F) -> &mut Router {
+        let param = glob.as_ref().rsplit('/').next().and_then(param_name).map(|name| name.to_string())
+            .unwrap_or_else(|| panic!("Static file glob {} has to end with a parameter", glob.as_ref()));
+        let route_id = format!("static:{}", glob.as_ref());
+
+        self.get(glob.as_ref(), &route_id);
+        self.static_files.insert(route_id, (files.into(), param));
+        self
+    }
+
+    /// Serve the routes of `route_id` under `glob` too, without a redirect.
+    ///
+    /// The handler is called with the same route id, `url_for` keeps linking to the original glob.
+    /// Parameters are passed on by name, so `glob` should use the names of the original glob.
+    /// Panics if there is no such route or if it belongs to a nested router.
+    pub fn alias<S: AsRef<str>>(&mut self, glob: S, route_id: &str) -> &mut Router {
+        let pattern = parse_glob(glob.as_ref());
+        let original = self.route_ids.get(route_id).cloned()
+            .unwrap_or_else(|| panic!("No route with that ID: {}", route_id));
+
+        let routes: Vec<(Option<method::Method>, Route)> = self.matcher.get(&original).into_iter()
+            .flat_map(|methods| methods.iter())
+            .flat_map(|(method, routes)| routes.iter()
+                .filter(|r| r.id == route_id)
+                .map(move |r| (method.clone(), r.clone())))
+            .collect();
+        if routes.is_empty() {
+            panic!("Route {} belongs to a nested router", route_id);
+        }
+
+        for (method, route) in routes {
+            add_route(self.mut_matcher().entry(pattern.clone(), HashMap::new).entry(method).or_default(), route);
+        }
+        self.aliases.entry(route_id.to_string()).or_default().push(glob.as_ref().to_string());
+        self
+    }
+
+    /// Remove the routes of `route_id` from `glob`, only for `method` or including `any` routes if `method` is `None`.
+    ///
+    /// Globs left without routes are removed.
+    fn take_routes(&mut self, glob: &str, route_id: &str, method: Option<&method::Method>) -> Vec<(Option<method::Method>, Route)> {
+        let mut routes = Vec::new();
+
+        if let Some(methods) = self.mut_matcher().get_mut(glob) {
+            for (m, list) in methods.iter_mut() {
+                if method.is_none_or(|method| m.as_ref() == Some(method)) {
+                    let (taken, kept): (Vec<Route>, Vec<Route>) = list.drain(..).partition(|r| r.id == route_id);
+                    *list = kept;
+                    routes.extend(taken.into_iter().map(|r| (m.clone(), r)));
+                }
+            }
+            methods.retain(|_, list| !list.is_empty());
+        }
+        if self.matcher.get(glob).is_some_and(|methods| methods.is_empty()) {
+            self.mut_matcher().remove(glob);
+        }
+
+        routes
+    }
+
+    /// Check if `route_id` still has routes on `glob` or in a nested router.
+    fn has_routes(&self, glob: &str, route_id: &str) -> bool {
+        self.matcher.get(glob).is_some_and(|methods| methods.values().flatten().any(|r| r.id == route_id)) ||
+            self.nested.iter().any(|(_, router)| router.route_ids.contains_key(route_id))
+    }
+
+    /// The glob `route_id` is registered with.
+    pub fn glob(&self, route_id: &str) -> Option<&str> {
+        self.route_ids.get(route_id).map(|glob| glob.as_str())
+    }
+
+    /// All route ids with their globs, ordered by route id.
+    pub fn routes(&self) -> Vec<(&str, &str)> {
+        let mut routes: Vec<(&str, &str)> = self.route_ids.iter()
+            .map(|(id, glob)| (id.as_str(), glob.as_str()))
+            .collect();
+        routes.sort();
+        routes
+    }
+
     fn route_id(&mut self, id: &str, glob: &str) {
         match self.route_ids.get(id) {
             Some(other_glob) if glob != other_glob => panic!("Duplicate route_id: {}", id),
@@ -92,65 +761,139 @@ impl Router {
         self.route(method::Options, glob, route_id)
     }
 
+    pub fn trace<S: AsRef<str>>(&mut self, glob: S, route_id: &str) -> &mut Router {
+        self.route(method::Trace, glob, route_id)
+    }
+
+    /// Register a route for a method given by name, e.g. the WebDAV methods `PROPFIND` or `MKCOL`.
+    ///
+    /// Panics if `method` is not a valid method name.
+    pub fn extension<S: AsRef<str>>(&mut self, method: &str, glob: S, route_id: &str) -> &mut Router {
+        let method = method.parse().unwrap_or_else(|_| panic!("Invalid method: {}", method));
+        self.route(method, glob, route_id)
+    }
+
     pub fn any<S: AsRef<str>>(&mut self, glob: S, route_id: &str) -> &mut Router {
-        self.wildcard.add(glob.as_ref(), route_id.to_string());
-        self.route_id(route_id.as_ref(), glob.as_ref());
-        self
+        self.any_when(glob, route_id, Vec::new())
     }
 
-    fn recognize(&self, method: &method::Method, path: &str) -> Result<Match<String>, RouterError> {
-        match self.matcher.recognize(path)
-            .map(|s|
-                match s.handler.get(method) {
-                    Some(h) => Ok(Match::new(h.to_string(), s.params)),
-                    None => self.wildcard.recognize(path).ok()
-                        .map_or(Err(RouterError::MethodNotAllowed), |s| Ok(Match::new(s.handler.to_string(), s.params)))
+    /// Register a route for any method that only matches if all `guards` are satisfied by the request.
+    pub fn any_when<S: AsRef<str>>(&mut self, glob: S, route_id: &str, guards: Vec<Guard>) -> &mut Router {
+        self.add(None, glob.as_ref(), Route { id: route_id.to_string(), guards, version: None, locale: None })
+    }
+
+    /// Recognize the route for `method` and `path`.
+    ///
+    /// Globs are tried best match first. Within a glob a route for the method wins over an
+    /// `any` route, HEAD requests fall back to the GET routes in between.
+    fn recognize(&self, method: &method::Method, path: &str, parts: &RequestParts) -> Result<Match<MatchedRoute>, Rejection> {
+        let matches = self.matcher.recognize(path);
+        let mut error = if matches.is_empty() { Rejection::NotFound } else { Rejection::MethodNotAllowed };
+        let mut found = None;
+
+        let mut candidates = vec![Some(method.clone())];
+        if *method == method::Head {
+            candidates.push(Some(method::Get));
+        }
+        candidates.push(None);
+
+        // A glob without a route for the method falls through to the next one.
+        'globs: for m in matches {
+            for candidate in &candidates {
+                if let Some(routes) = m.handler.get(candidate) {
+                    match select(routes, parts) {
+                        Ok(r) => {
+                            found = Some((r.clone(), m.params, candidate));
+                            break 'globs;
+                        },
+                        Err(e) => error = error.or(e)
+                    }
                 }
-            ).map_err(|_| self.wildcard.recognize(path).ok()
-            .map_or(Err(RouterError::NotFound), |s| Ok(Match::new(s.handler.to_string(), s.params)))) {
-            Ok(s) => s,
-            Err(e) => e
+            }
         }
+
+        let (route, params, candidate) = found.ok_or(error)?;
+        let matched = MatchedRoute {
+            locale: route.locale,
+            glob: self.route_ids.get(&route.id).cloned().unwrap_or_default(),
+            route_id: route.id,
+            method: candidate.clone().unwrap_or_else(|| method.clone()),
+            wildcard: candidate.is_none(),
+            version: route.version,
+            handler: None,
+            head_fallback: *method == method::Head && *candidate == Some(method::Get)
+        };
+
+        Ok(Match::new(matched, params))
     }
 
     fn handlers(&self, req: &mut Request, route_id: String) -> IronResult<Response> {
 
-        for x in &self.handlers {
-            match x.handle(req, &route_id) {
+        for (index, x) in self.handlers.iter().enumerate() {
+            if let Some(matched) = req.extensions.get_mut::<MatchedRoute>() {
+                matched.handler = Some(index);
+            }
+
+            match self.call_handler(&route_id, || x.handle(req, &route_id)) {
                 Ok(h) => {
                     return Ok(h)
                 },
-                Err(err) => {
-                    match err.error.downcast::<RouterError>() {
+                Err(mut err) => {
+                    let status = match err.error.downcast::<RouterError>() {
                         Some(&RouterError::NextMiddleware) => {
+                            if let Some(matched) = req.extensions.get_mut::<MatchedRoute>() {
+                                matched.handler = None;
+                            }
                             continue;
                         },
-                        Some(&RouterError::NotFound) => {
-                            return Err(IronError::new(RouterError::NotFound, status::NotFound))
-                        },
-                        Some(&RouterError::TrailingSlash) => {
-                            return Err(IronError::new(RouterError::TrailingSlash, status::NotFound))
-                        },
-                        Some(&RouterError::MethodNotAllowed) => {
-                            return Err(IronError::new(RouterError::MethodNotAllowed, status::MethodNotAllowed))
-                        },
-                        Some(&RouterError::BadRequest) => {
-                            return Err(IronError::new(RouterError::BadRequest, status::BadRequest))
-                        },
-                        None => {
-                            return Err(err)
-                        }
-                    }
+                        Some(e) => e.status(),
+                        None => return Err(err)
+                    };
+
+                    // A `RouterError` from a handler always gets its own status.
+                    err.response.status = Some(status);
+                    return Err(err)
                 }
             }
         }
 
-        Err(IronError::new(RouterError::NotFound, status::NotFound))
+        Err(RouterError::not_found(req).into())
+    }
+
+    /// Call a handler, catching its panics if enabled.
+    fn call_handler<F: FnOnce() -> IronResult<Response>>(&self, route_id: &str, handle: F) -> IronResult<Response> {
+        if !self.catch_panics {
+            return handle();
+        }
+
+        panic::catch_unwind(panic::AssertUnwindSafe(handle)).unwrap_or_else(|payload| {
+            let message = payload.downcast_ref::<&str>().map(|m| m.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            if let Some(ref hook) = self.panic_hook {
+                hook(route_id, &message);
+            }
+
+            Err(RouterError::HandlerPanicked { route_id: route_id.to_string() }.into())
+        })
+    }
+
+    /// Redirect to the route `route_id` with the parameters of the matched route.
+    fn redirect_to(&self, req: &Request, route_id: &str, status: status::Status) -> IronResult<Response> {
+        if !self.route_ids.contains_key(route_id) {
+            return Err(RouterError::not_found(req).into());
+        }
+
+        let params: Vec<(String, String)> = req.extensions.get::<Router>()
+            .map(|params| params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+            .unwrap_or_default();
+        let url = url_for(req, route_id, UrlParams::from(params).keep_query());
+        Ok(Response::with((status, Redirect(url))))
     }
 
-    fn redirect_slash(&self, req: &Request) -> Option<IronError> {
+    fn redirect_slash(&self, req: &Request, path: &str) -> Option<IronError> {
         let mut url = req.url.clone();
-        let mut path = url.path().join("/");
+        let mut path = path.to_string();
 
         if let Some(original) = req.extensions.get::<mount::OriginalUrl>() {
             url =  original.clone();
@@ -169,63 +912,258 @@ impl Router {
                 }
         }
 
-        self.recognize(&req.method, &path).ok().and(
-            Some(IronError::new(RouterError::TrailingSlash,
+        self.recognize(&req.method, &path, &RequestParts::new(req)).ok().and(
+            Some(IronError::new(RouterError::TrailingSlash { path: requested_path(req) },
                                 (status::MovedPermanently, Redirect(url))))
         )
     }
 
-    fn handle_options(&self, path: &str) -> Response {
-        static METHODS: &'static [method::Method] =
-        &[method::Get, method::Post, method::Put,
-            method::Delete, method::Head, method::Patch];
+    fn handle_options(&self, path: &str) -> Response {
+        let mut res = Response::with(status::Ok);
+        res.headers.set(headers::Allow(self.allowed_methods(path)));
+        res
+    }
+
+    /// The methods registered for `path`.
+    fn allowed_methods(&self, path: &str) -> Vec<method::Method> {
+        static METHODS: &[method::Method] =
+        &[method::Get, method::Post, method::Put,
+            method::Delete, method::Head, method::Patch,
+            method::Options, method::Connect, method::Trace];
+
+        // Get all the registered methods and return them, extension methods sorted by name.
+        let mut options: Vec<method::Method> = vec![];
+        for m in self.matcher.recognize(path) {
+            for method in m.handler.keys() {
+                // An `any` route allows all methods.
+                let methods = method.as_ref().map_or(METHODS, ::std::slice::from_ref);
+                options.extend(methods.iter().filter(|method| !options.contains(method)).cloned().collect::<Vec<_>>());
+            }
+        }
+
+        options.sort_by_key(|method| match METHODS.iter().position(|m| m == method) {
+            Some(position) => (position, String::new()),
+            None => (METHODS.len(), method.to_string())
+        });
+        // If GET is there, HEAD is also there.
+        if options.contains(&method::Get) && !options.contains(&method::Head) {
+            options.push(method::Head);
+        }
+
+        options
+    }
+
+    /// The error for a request to `path` for which no route was recognized.
+    fn rejection_error(&self, req: &Request, routed_path: &str, rejection: Rejection) -> RouterError {
+        let (path, method) = (requested_path(req), req.method.clone());
+        match rejection {
+            Rejection::NotFound => RouterError::NotFound { path, method },
+            Rejection::MethodNotAllowed => RouterError::MethodNotAllowed { path, method, allowed: self.allowed_methods(routed_path) },
+            Rejection::UnsupportedMediaType => RouterError::UnsupportedMediaType { path, method },
+            Rejection::NotAcceptable => RouterError::NotAcceptable { path, method }
+        }
+    }
+
+    fn handle_method(&self, req: &mut Request, path: &str) -> IronResult<Response> {
+        let recognized = self.recognize(&req.method, path, &RequestParts::new(req));
+
+        match recognized {
+            Ok(matched) => {
+                let route_id = matched.handler.route_id.clone();
+                req.extensions.insert::<Router>(matched.params);
+                req.extensions.insert::<RouteMap>(self.route_ids.clone());
+                if !self.locales.is_empty() {
+                    let locale = self.detect_locale(req, path, matched.handler.locale.as_ref());
+                    req.extensions.insert::<Locale>(locale);
+                    req.extensions.insert::<LocaleMap>(self.translations.clone());
+                }
+                req.extensions.insert::<MatchedRoute>(matched.handler);
+                if let Some((target, status)) = self.redirects.get(&route_id) {
+                    return self.redirect_to(req, target, *status);
+                }
+                if self.sitemap_route.as_ref() == Some(&route_id) {
+                    return self.serve_sitemap_route(req, &route_id);
+                }
+                if let Some((files, param)) = self.static_files.get(&route_id) {
+                    let path = req.extensions.get::<Router>().and_then(|p| p.find(param)).unwrap_or("").to_string();
+                    return files.serve(&req.headers, &path).ok_or_else(|| RouterError::not_found(req).into());
+                }
+                self.handlers(req, route_id)
+            },
+            Err(Rejection::MethodNotAllowed) if req.method == method::Options => Ok(self.handle_options(path)),
+            Err(rejection) if rejection != Rejection::NotFound => {
+                Err(self.rejection_error(req, path, rejection).into())
+            },
+            Err(_) => {
+                if let Some(response) = self.redirect_locale(req, path) {
+                    return Ok(response);
+                }
+                match self.redirect_slash(req, path) {
+                    Some(err) => Err(err),
+                    None => Err(RouterError::not_found(req).into())
+                }
+            }
+        }
+    }
+
+    /// The pages of the sitemap, linked with `link(route_id, locale, params)`.
+    fn sitemap_urls<F: Fn(&str, Option<&str>, UrlParams) -> Url>(&self, link: F) -> Vec<SitemapUrl> {
+        let mut route_ids: Vec<&String> = self.sitemap.keys().collect();
+        route_ids.sort();
+
+        let mut urls = Vec::new();
+        for route_id in route_ids {
+            let entry = &self.sitemap[route_id];
+            let pages = entry.params.as_ref().map_or_else(|| vec![UrlParams::new()], |provider| provider());
+            let locales: Vec<Option<&str>> = match self.translations.get(route_id) {
+                Some(translations) => translations.iter().map(|(locale, _)| Some(locale.as_str())).collect(),
+                None => vec![None]
+            };
+
+            for params in pages {
+                for &locale in &locales {
+                    urls.push(SitemapUrl {
+                        loc: link(route_id, locale, params.clone()).to_string(),
+                        changefreq: entry.changefreq,
+                        priority: entry.priority
+                    });
+                }
+            }
+        }
+        urls
+    }
+
+    fn serve_sitemap_route(&self, req: &Request, route_id: &str) -> IronResult<Response> {
+        let urls = self.sitemap_urls(|route_id, locale, params| match locale {
+            Some(locale) => url_for_locale(req, route_id, locale, params),
+            None => url_for(req, route_id, params)
+        });
+
+        let xml = if urls.len() <= sitemap::MAX_URLS {
+            sitemap::urlset(&urls)
+        } else {
+            let parts: Vec<&[SitemapUrl]> = urls.chunks(sitemap::MAX_URLS).collect();
+            match query::<usize>(req, "page") {
+                None => {
+                    let locs: Vec<String> = (1..parts.len() + 1)
+                        .map(|page| url_for(req, route_id, UrlParams::new().with("page", page.to_string())).to_string())
+                        .collect();
+                    sitemap::index(&locs)
+                },
+                Some(page) => match page.checked_sub(1).and_then(|i| parts.get(i)) {
+                    Some(part) => sitemap::urlset(part),
+                    None => return Err(RouterError::not_found(req).into())
+                }
+            }
+        };
+
+        let mut response = Response::with((status::Ok, xml));
+        response.headers.set_raw("Content-Type", vec![b"application/xml; charset=utf-8".to_vec()]);
+        Ok(response)
+    }
+
+    /// The locale prefix of `path`, if it is one of the locales.
+    fn locale_prefix<'a>(&self, path: &'a str) -> Option<&'a str> {
+        path.split('/').next().filter(|segment| self.locales.iter().any(|l| l == segment))
+    }
+
+    /// The locale of a request: the one of a localized route, the locale prefix of the path, the
+    /// one `Accept-Language` prefers or the default locale.
+    fn detect_locale(&self, req: &Request, path: &str, route_locale: Option<&String>) -> Locale {
+        if let Some(locale) = route_locale.map(|l| l.as_str()).or_else(|| self.locale_prefix(path)) {
+            return Locale { locale: locale.to_string(), source: LocaleSource::PathPrefix };
+        }
+
+        match locale::negotiate(&RequestParts::new(req), &self.locales) {
+            Some(locale) => Locale { locale, source: LocaleSource::AcceptLanguage },
+            None => Locale { locale: self.locales[0].clone(), source: LocaleSource::Default }
+        }
+    }
+
+    /// Redirect a request to a path without locale prefix to the path in the locale
+    /// `Accept-Language` prefers, or the default locale, if the router has it.
+    fn redirect_locale(&self, req: &Request, path: &str) -> Option<Response> {
+        if self.locales.is_empty() || self.locale_prefix(path).is_some() {
+            return None;
+        }
+
+        let locale = locale::negotiate(&RequestParts::new(req), &self.locales)
+            .unwrap_or_else(|| self.locales[0].clone());
+        let localized = join_path(&locale, path);
+        self.recognize(&req.method, &localized, &RequestParts::new(req)).ok()?;
+
+        // Insert the locale in front of the routed path, behind prefixes of mounting layers.
+        let mut url = req.extensions.get::<mount::OriginalUrl>().unwrap_or(&req.url).clone();
+        let mut segments: Vec<String> = url.path().iter().map(|s| s.to_string()).collect();
+        let routed = if path.is_empty() {
+            segments.retain(|s| !s.is_empty());
+            0
+        } else {
+            path.split('/').count()
+        };
+        let at = segments.len().saturating_sub(routed);
+        segments.insert(at, locale);
+        url.as_mut().path_segments_mut().unwrap().clear().extend(&segments);
+
+        let mut response = Response::with((status::Found, Redirect(url)));
+        response.headers.set_raw("Vary", vec![b"Accept-Language".to_vec()]);
+        Some(response)
+    }
 
-        // Get all the available methods and return them.
-        let mut options = vec![];
+    /// The nested router handling `path` with its prefix and the rest of the path.
+    ///
+    /// The longest prefix with a route for the path wins, e.g. `/api` if the router nested at
+    /// `/api/admin` has none. A path stays with this router if only this router has a route for it,
+    /// if no router has one the longest prefix answers.
+    fn nested_router<'a, 'p>(&'a self, method: &method::Method, path: &'p str, parts: &RequestParts)
+        -> Option<(&'a str, &'a Router, &'p str)>
+    {
+        let candidates: Vec<(&str, &Router, &str)> = self.nested.iter()
+            .filter_map(|(prefix, router)| strip_prefix(path, prefix).map(|rest| (prefix.as_str(), router, rest)))
+            .collect();
 
-        for method in METHODS.iter() {
-            if let Ok(s) = self.matcher.recognize(path) {
-                if let Some(_) = s.handler.get(method) {
-                    options.push(method.clone());
-                }
-            }
+        if let Some(&found) = candidates.iter().find(|&&(_, router, rest)| router.has_route(method, rest, parts)) {
+            return Some(found);
         }
-        // If GET is there, HEAD is also there.
-        if options.contains(&method::Get) && !options.contains(&method::Head) {
-            options.push(method::Head);
+        if matches!(self.recognize(method, path, parts), Err(Rejection::NotFound)) {
+            candidates.first().cloned()
+        } else {
+            None
         }
+    }
 
-        let mut res = Response::with(status::Ok);
-        res.headers.set(headers::Allow(options));
-        res
+    /// Whether this router or a nested one has a route for `path`, maybe not for `method`.
+    fn has_route(&self, method: &method::Method, path: &str, parts: &RequestParts) -> bool {
+        let path = if self.versions.is_enabled() { self.versions.resolve(path, parts).1 } else { path };
+        let nested = self.nested.iter()
+            .filter_map(|(prefix, router)| strip_prefix(path, prefix).map(|rest| (router, rest)))
+            .any(|(router, rest)| router.has_route(method, rest, parts));
+        nested || !matches!(self.recognize(method, path, parts), Err(Rejection::NotFound))
     }
 
-    fn handle_method(&self, req: &mut Request, path: &str) -> IronResult<Response> {
-        match self.recognize(&req.method, &path) {
-            Ok(matched) => {
-                req.extensions.insert::<Router>(matched.params);
-                req.extensions.insert::<RouteMap>(self.route_ids.clone());
-                self.handlers(req, matched.handler)
-            },
-            Err(RouterError::MethodNotAllowed) => {
-                Err(IronError::new(RouterError::MethodNotAllowed, status::MethodNotAllowed))
-            },
-            Err(_) => {
-                match self.redirect_slash(req) {
-                    Some(err) => Err(err),
-                    None =>
-                        match req.method {
-                            method::Options => Ok(self.handle_options(&path)),
-                            // For HEAD, fall back to GET. Hyper ensures no response body is written.
-                            method::Head => {
-                                req.method = method::Get;
-                                self.handle_method(req, path)
-                            },
-                            _ => Err(IronError::new(RouterError::NotFound, status::NotFound))
-                        }
-                }
+    /// Route the request by `path`, `base` is the part of the routed path in front of it.
+    fn dispatch(&self, req: &mut Request, path: &str, base: &str) -> IronResult<Response> {
+        override_method(req, &self.method_override);
+
+        let mut path = path;
+        let mut base = base.to_string();
+
+        if self.versions.is_enabled() {
+            let (version, rest) = self.versions.resolve(path, &RequestParts::new(req));
+            if let Some(version) = version {
+                req.extensions.insert::<ApiVersion>(version);
             }
+            base = join_path(&base, &path[..path.len() - rest.len()]);
+            path = rest;
+        }
+
+        let nested = self.nested_router(&req.method, path, &RequestParts::new(req));
+        if let Some((prefix, router, rest)) = nested {
+            return router.dispatch(req, rest, &join_path(&base, prefix));
         }
+
+        req.extensions.insert::<BasePath>(base);
+        self.handle_method(req, path)
     }
 }
 
@@ -240,55 +1178,272 @@ pub fn requested_url(req: &mut Request) -> Url {
     }
 }
 
+/// The path of the request before any mounting layer or nested router stripped a prefix.
+fn requested_path(req: &Request) -> String {
+    let url = req.extensions.get::<mount::OriginalUrl>().unwrap_or(&req.url);
+    format!("/{}", url.path().join("/"))
+}
+
+/// Strip the segments of `prefix` from the front of `path`.
+fn strip_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    if path == prefix {
+        Some("")
+    } else if path.starts_with(prefix) && path[prefix.len()..].starts_with('/') {
+        Some(&path[prefix.len() + 1..])
+    } else {
+        None
+    }
+}
+
+fn join_path(base: &str, path: &str) -> String {
+    let path = path.trim_matches('/');
+    match (base.is_empty(), path.is_empty()) {
+        (_, true) => base.to_string(),
+        (true, false) => path.to_string(),
+        (false, false) => format!("{}/{}", base, path)
+    }
+}
+
 impl Handler for Router {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let head = req.method == method::Head;
         let path = req.url.path().join("/");
-        self.handle_method(req, &path)
+        let mut result = self.dispatch(req, &path, "");
+
+        if let (true, Err(ref mut err)) = (self.problem_details, result.as_mut()) {
+            let instance = requested_url(req).to_string();
+            render_error(err, &req.headers, instance);
+        }
+
+        if head {
+            match result {
+                Ok(ref mut response) => strip_body(response),
+                Err(ref mut err) => strip_body(&mut err.response)
+            }
+        }
+        result
+    }
+}
+
+/// A body which writes nothing.
+struct EmptyBody;
+
+impl WriteBody for EmptyBody {
+    fn write_body(&mut self, _: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Counts the bytes of a body.
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Remove the body of a response to a HEAD request, keeping the `Content-Length` of the body.
+fn strip_body(response: &mut Response) {
+    if let Some(mut body) = response.body.take() {
+        if !response.headers.has::<headers::ContentLength>() {
+            let mut counter = ByteCounter(0);
+            if body.write_body(&mut counter).is_ok() {
+                response.headers.set(headers::ContentLength(counter.0));
+            }
+        }
+        response.body = Some(Box::new(EmptyBody));
     }
 }
 
+/// Render `err` as a problem document if it is a `RouterError`, redirects are left as they are.
+fn render_error(err: &mut IronError, request_headers: &headers::Headers, instance: String) {
+    if err.response.headers.has::<headers::Location>() {
+        return;
+    }
+
+    let problem = match err.error.downcast::<RouterError>() {
+        Some(error) => error.problem().instance(instance),
+        None => return
+    };
+
+    problem.render(request_headers, &mut err.response);
+}
+
 impl Key for Router { type Value = Params; }
 
 pub struct RouteMap;
 impl Key for RouteMap { type Value = HashMap<String, String>; }
 
+/// The part of the routed path consumed before the routes of the handling router were matched,
+/// i.e. the prefixes of nested routers and a version prefix like `v2`.
+pub struct BasePath;
+impl Key for BasePath { type Value = String; }
+
+/// The path prefix a mounting layer in front of the router stripped from the request.
+///
+/// Mounting layers can set it, so `url_for` doesn't have to guess the prefix from `mount::OriginalUrl`.
+pub struct MountPath;
+impl Key for MountPath { type Value = String; }
+
+/// Describes the route that matched the current request.
+///
+/// It is inserted into the request extensions before the handlers are called, so
+/// middleware running after the router can find out which route served the request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchedRoute {
+    /// The `route_id` used during route registration.
+    pub route_id: String,
+    /// The glob the route was registered with.
+    pub glob: String,
+    /// The method the route was recognized with.
+    pub method: method::Method,
+    /// `true` if the route was registered with `Router::any`.
+    pub wildcard: bool,
+    /// The API version the route was registered for with `Router::versioned`.
+    pub version: Option<u32>,
+    /// Index (in `link` order) of the `RouteHandler` that served the request, if any.
+    pub handler: Option<usize>,
+    /// `true` if a HEAD request is served by a GET route, the router strips the body of the response.
+    pub head_fallback: bool,
+    /// The locale of the translation registered with `Router::localized`.
+    pub locale: Option<String>
+}
+
+impl Key for MatchedRoute { type Value = MatchedRoute; }
+
+/// The errors of the router, and errors handlers can return to get the router's response.
 #[derive(Debug, PartialEq)]
 pub enum RouterError {
     /// The error thrown by router if there is no matching method in existing route.
-    MethodNotAllowed,
+    MethodNotAllowed { path: String, method: method::Method, allowed: Vec<method::Method> },
     /// The error thrown by router if there is no matching route.
-    NotFound,
+    NotFound { path: String, method: method::Method },
     /// The error thrown by router if a request was redirected by adding or removing a trailing slash.
-    TrailingSlash,
+    TrailingSlash { path: String },
     /// This middleware not support that route_id
     NextMiddleware,
-    ///
-    BadRequest
+    /// The request is not valid for the matched route, `param` is the offending parameter if known.
+    BadRequest { param: Option<String>, reason: String },
+    /// The error thrown by router if no route accepts the `Content-Type` of the request.
+    UnsupportedMediaType { path: String, method: method::Method },
+    /// The error thrown by router if no route responds with a media type the request accepts.
+    NotAcceptable { path: String, method: method::Method },
+    /// The error thrown by router if the handler of the route panicked, see `Router::catch_panics`.
+    HandlerPanicked { route_id: String }
 }
 
-
 impl fmt::Display for RouterError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.description())
+        match *self {
+            RouterError::MethodNotAllowed { ref path, ref method, ref allowed } => {
+                let allowed: Vec<String> = allowed.iter().map(|m| m.to_string()).collect();
+                write!(f, "Method {} is not allowed for {}, allowed are {}", method, path, allowed.join(", "))
+            },
+            RouterError::NotFound { ref path, ref method } => write!(f, "No route found for {} {}", method, path),
+            RouterError::TrailingSlash { ref path } => write!(f, "The request to {} was redirected for its trailing slash", path),
+            RouterError::BadRequest { param: Some(ref param), ref reason } => write!(f, "Invalid parameter {}: {}", param, reason),
+            RouterError::BadRequest { param: None, ref reason } => write!(f, "Invalid request: {}", reason),
+            RouterError::UnsupportedMediaType { ref path, ref method } =>
+                write!(f, "No route for {} {} accepts the Content-Type of the request", method, path),
+            RouterError::NotAcceptable { ref path, ref method } =>
+                write!(f, "No route for {} {} responds with a media type the request accepts", method, path),
+            RouterError::HandlerPanicked { ref route_id } => write!(f, "The handler of route {} panicked", route_id),
+            RouterError::NextMiddleware => f.write_str(self.title())
+        }
     }
 }
 
-impl Error for RouterError {
-    fn description(&self) -> &str {
+impl RouterError {
+    /// A `NotFound` for the path and method of `req`.
+    pub fn not_found(req: &Request) -> RouterError {
+        RouterError::NotFound { path: requested_path(req), method: req.method.clone() }
+    }
+
+    /// A `BadRequest` for the parameter `param`.
+    pub fn bad_request<P: Into<String>, R: Into<String>>(param: P, reason: R) -> RouterError {
+        RouterError::BadRequest { param: Some(param.into()), reason: reason.into() }
+    }
+
+    /// The status of the response for the error.
+    pub fn status(&self) -> status::Status {
+        match *self {
+            RouterError::MethodNotAllowed { .. } => status::MethodNotAllowed,
+            RouterError::NotFound { .. } => status::NotFound,
+            // Only the router's own redirect has a `Location`, a bare `TrailingSlash` is not found.
+            RouterError::TrailingSlash { .. } => status::NotFound,
+            RouterError::NextMiddleware => status::NotFound,
+            RouterError::BadRequest { .. } => status::BadRequest,
+            RouterError::UnsupportedMediaType { .. } => status::UnsupportedMediaType,
+            RouterError::NotAcceptable { .. } => status::NotAcceptable,
+            RouterError::HandlerPanicked { .. } => status::InternalServerError
+        }
+    }
+
+    /// The error as a problem document, see `Problem`.
+    ///
+    /// The context of the error becomes additional members, e.g. `allowed` for `MethodNotAllowed`.
+    pub fn problem(&self) -> Problem {
+        let problem = Problem::new(self.status()).detail(self.to_string());
+        match *self {
+            RouterError::MethodNotAllowed { ref path, ref method, ref allowed } => problem
+                .extension("path", Member::String(path.clone()))
+                .extension("method", Member::String(method.to_string()))
+                .extension("allowed", Member::List(allowed.iter().map(|m| m.to_string()).collect())),
+            RouterError::NotFound { ref path, ref method } |
+            RouterError::UnsupportedMediaType { ref path, ref method } |
+            RouterError::NotAcceptable { ref path, ref method } => problem
+                .extension("path", Member::String(path.clone()))
+                .extension("method", Member::String(method.to_string())),
+            RouterError::TrailingSlash { ref path } => problem.extension("path", Member::String(path.clone())),
+            RouterError::BadRequest { param: Some(ref param), .. } => problem.extension("param", Member::String(param.clone())),
+            RouterError::HandlerPanicked { ref route_id } => problem.extension("route_id", Member::String(route_id.clone())),
+            RouterError::BadRequest { param: None, .. } | RouterError::NextMiddleware => problem
+        }
+    }
+
+    fn title(&self) -> &'static str {
         match *self {
-            RouterError::MethodNotAllowed => "Method Not Allowed",
-            RouterError::NotFound => "No matching route found.",
-            RouterError::TrailingSlash => "The request had a trailing slash.",
+            RouterError::MethodNotAllowed { .. } => "Method Not Allowed",
+            RouterError::NotFound { .. } => "No matching route found.",
+            RouterError::TrailingSlash { .. } => "The request had a trailing slash.",
             RouterError::NextMiddleware => "This is middleware not support this request",
-            RouterError::BadRequest => "This is not valid request"
+            RouterError::BadRequest { .. } => "This is not valid request",
+            RouterError::UnsupportedMediaType { .. } => "Unsupported Media Type",
+            RouterError::NotAcceptable { .. } => "Not Acceptable",
+            RouterError::HandlerPanicked { .. } => "The route handler panicked"
+        }
+    }
+}
+
+impl Error for RouterError {
+    fn description(&self) -> &str {
+        self.title()
+    }
+}
+
+impl From<RouterError> for IronError {
+    /// An `IronError` with the status of the error, and the `Allow` header for `MethodNotAllowed`.
+    fn from(error: RouterError) -> IronError {
+        let mut response = Response::with(error.status());
+        if let RouterError::MethodNotAllowed { ref allowed, .. } = error {
+            response.headers.set(headers::Allow(allowed.clone()));
         }
+
+        IronError { error: Box::new(error), response }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Router, RouterError, RouteHandler};
-    use iron::{headers, method, status, Request, Response, IronError, IronResult};
+    use super::{Router, RouterError, Rejection, RouteHandler, strip_prefix, join_path, render_error, strip_body};
+    use guard::{Guard, RequestParts};
+    use iron::{headers, method, status, Request, Response, IronError, IronResult, Url};
 
     struct DummyController;
 
@@ -339,18 +1494,38 @@ mod test {
         let expected = headers::Allow(vec![method::Method::Get, method::Method::Head]);
         assert_eq!(&expected, headers);
     }
+    #[test]
+    fn test_handle_options_extension() {
+        let mut router = Router::new();
+        router.extension("PROPFIND", "/dav", "propfind");
+        router.extension("MKCOL", "/dav", "mkcol");
+        router.trace("/dav", "trace");
+        router.get("/dav", "get");
+        router.extension("PUT", "/dav", "put");
+        let resp = router.handle_options("/dav");
+        let headers = resp.headers.get::<headers::Allow>().unwrap();
+        let expected = headers::Allow(vec![method::Get, method::Put, method::Trace,
+                                           method::Extension("MKCOL".to_string()),
+                                           method::Extension("PROPFIND".to_string()),
+                                           method::Head]);
+        assert_eq!(&expected, headers);
+
+        let propfind = method::Extension("PROPFIND".to_string());
+        assert_eq!(router.recognize(&propfind, "/dav", &RequestParts::default()).unwrap().handler.route_id, "propfind");
+    }
+
     #[test]
     fn test_not_allowed_method() {
         let mut router = Router::new();
         router.link(DummyController);
         router.post("/post","someone");
         router.get("/post/", "another_route");
-        match router.recognize(&method::Get, "/post") {
+        match router.recognize(&method::Get, "/post", &RequestParts::default()) {
             Ok(_) => {
                panic!();
             },
             Err(e) => {
-               assert_eq!(RouterError::MethodNotAllowed, e);
+               assert_eq!(Rejection::MethodNotAllowed, e);
             }
         }
     }
@@ -364,8 +1539,502 @@ mod test {
         router.put("/post", "anys");
         router.any("/get", "another");
 
-        assert!(router.recognize(&method::Get, "/post").is_ok());
-        assert!(router.recognize(&method::Get, "/get").is_ok());
+        assert!(router.recognize(&method::Get, "/post", &RequestParts::default()).is_ok());
+        assert!(router.recognize(&method::Get, "/get", &RequestParts::default()).is_ok());
+    }
+
+    #[test]
+    fn test_any_precedence() {
+        let mut router = Router::new();
+        router.get("/users/:id", "users.show");
+        router.any("/users/:id", "users.any");
+        router.get("/files/*path", "files.show");
+        router.any("/files/:name", "files.any");
+
+        let parts = RequestParts::default();
+        let route_id = |method: method::Method, path: &str| router.recognize(&method, path, &parts).unwrap().handler.route_id;
+        assert_eq!(route_id(method::Get, "/users/1"), "users.show");
+        assert_eq!(route_id(method::Head, "/users/1"), "users.show");
+        assert_eq!(route_id(method::Delete, "/users/1"), "users.any");
+        assert_eq!(route_id(method::Get, "/files/a.txt"), "files.any");
+        assert_eq!(route_id(method::Get, "/files/a/b.txt"), "files.show");
+    }
+
+    #[test]
+    fn test_head() {
+        let mut router = Router::new();
+        router.get("/users", "users.index");
+        router.head("/teams", "teams.head").get("/teams", "teams.index");
+        router.any("/health", "health");
+        router.any("/*path", "fallback");
+
+        let parts = RequestParts::default();
+        let matched = router.recognize(&method::Head, "/users", &parts).unwrap().handler;
+        assert_eq!((matched.route_id.as_str(), matched.method, matched.head_fallback), ("users.index", method::Get, true));
+        let matched = router.recognize(&method::Head, "/teams", &parts).unwrap().handler;
+        assert_eq!((matched.route_id.as_str(), matched.head_fallback), ("teams.head", false));
+        let matched = router.recognize(&method::Head, "/health", &parts).unwrap().handler;
+        assert_eq!((matched.route_id.as_str(), matched.head_fallback), ("health", false));
+
+        let allowed = router.handle_options("/health");
+        assert_eq!(allowed.headers.get::<headers::Allow>(), Some(&headers::Allow(vec![
+            method::Get, method::Post, method::Put, method::Delete, method::Head,
+            method::Patch, method::Options, method::Connect, method::Trace])));
+
+        let mut response = Response::with((status::Ok, "Hello"));
+        strip_body(&mut response);
+        assert_eq!(response.headers.get::<headers::ContentLength>(), Some(&headers::ContentLength(5)));
+        let mut body = Vec::new();
+        response.body.unwrap().write_body(&mut body).unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn test_matched_route() {
+        let mut router = Router::new();
+        router.link(DummySecondController);
+        router.post("/post/:id", "another");
+        router.any("/any", "anys");
+
+        let matched = router.recognize(&method::Post, "/post/1", &RequestParts::default()).unwrap().handler;
+        assert_eq!(matched.route_id, "another");
+        assert_eq!(matched.glob, "/post/:id");
+        assert_eq!(matched.method, method::Post);
+        assert!(!matched.wildcard);
+        assert_eq!(matched.handler, None);
+
+        let matched = router.recognize(&method::Delete, "/any", &RequestParts::default()).unwrap().handler;
+        assert_eq!(matched.route_id, "anys");
+        assert_eq!(matched.glob, "/any");
+        assert!(matched.wildcard);
+    }
+
+    #[test]
+    fn test_guards() {
+        let mut router = Router::new();
+        router.get("/api", "api");
+        router.route_when(method::Get, "/api", "api.list", vec![Guard::query_eq("action", "list")]);
+        router.route_when(method::Get, "/api", "api.create", vec![Guard::query_eq("action", "create")]);
+        router.any_when("/versioned", "v2", vec![Guard::header_eq("X-Api-Version", "2")]);
+
+        let recognize = |method: &method::Method, path: &str, query: &str| {
+            router.recognize(method, path, &RequestParts { query: Some(query), ..RequestParts::default() })
+                .map(|m| m.handler.route_id)
+        };
+
+        assert_eq!(recognize(&method::Get, "/api", "action=list"), Ok("api.list".to_string()));
+        assert_eq!(recognize(&method::Get, "/api", "action=create"), Ok("api.create".to_string()));
+        assert_eq!(recognize(&method::Get, "/api", "action=other"), Ok("api".to_string()));
+        assert_eq!(recognize(&method::Get, "/versioned", ""), Err(Rejection::NotFound));
+
+        let mut headers = headers::Headers::new();
+        headers.set_raw("X-Api-Version", vec![b"2".to_vec()]);
+        let parts = RequestParts { headers: Some(&headers), ..RequestParts::default() };
+        assert_eq!(router.recognize(&method::Put, "/versioned", &parts).unwrap().handler.route_id, "v2");
+    }
+
+    #[test]
+    fn test_versioned() {
+        let mut router = Router::new();
+        router.versioned(1, method::Get, "/users/:id", "users.show");
+        router.versioned(1, method::Get, "/users", "users.index");
+        router.versioned(2, method::Get, "/users/:id", "users.show");
+        router.versioned(3, method::Get, "/teams", "teams.index");
+
+        let recognize = |path: &str, version: u32| {
+            router.recognize(&method::Get, path, &RequestParts { version: Some(version), ..RequestParts::default() })
+                .map(|m| (m.handler.route_id, m.handler.version))
+        };
+
+        assert_eq!(recognize("/users/1", 1), Ok(("users.show".to_string(), Some(1))));
+        assert_eq!(recognize("/users/1", 2), Ok(("users.show".to_string(), Some(2))));
+        assert_eq!(recognize("/users/1", 5), Ok(("users.show".to_string(), Some(2))));
+        assert_eq!(recognize("/users", 3), Ok(("users.index".to_string(), Some(1))));
+        assert_eq!(recognize("/teams", 2), Err(Rejection::NotFound));
+    }
+
+    #[test]
+    fn test_content_negotiation() {
+        let mut router = Router::new();
+        router.route_when(method::Post, "/users", "users.create.json", vec![Guard::content_type("application/json")]);
+        router.route_when(method::Post, "/users", "users.create.form", vec![Guard::content_type("multipart/form-data")]);
+        router.route_when(method::Get, "/users", "users.json", vec![Guard::accept("application/json")]);
+        router.route_when(method::Get, "/users", "users.html", vec![Guard::accept("text/html")]);
+
+        let recognize = |method: &method::Method, header: &str, value: &str| {
+            let mut headers = headers::Headers::new();
+            headers.set_raw(header.to_string(), vec![value.as_bytes().to_vec()]);
+            router.recognize(method, "/users", &RequestParts { headers: Some(&headers), ..RequestParts::default() })
+                .map(|m| m.handler.route_id)
+        };
+
+        assert_eq!(recognize(&method::Post, "Content-Type", "application/json"), Ok("users.create.json".to_string()));
+        assert_eq!(recognize(&method::Post, "Content-Type", "multipart/form-data; boundary=x"), Ok("users.create.form".to_string()));
+        assert_eq!(recognize(&method::Post, "Content-Type", "text/plain"), Err(Rejection::UnsupportedMediaType));
+        assert_eq!(recognize(&method::Get, "Accept", "text/html, application/json;q=0.9"), Ok("users.html".to_string()));
+        assert_eq!(recognize(&method::Get, "Accept", "text/html;q=0.5, application/json"), Ok("users.json".to_string()));
+        assert_eq!(recognize(&method::Get, "Accept", "image/png"), Err(Rejection::NotAcceptable));
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut router = Router::new();
+        router.get("/files/*path", "files.any");
+        router.get("/files/:name", "files.show");
+        router.get("/files/:id<int>", "files.by_id");
+        router.get("/files/new", "files.new");
+        router.post("/files/:name", "files.update");
+
+        let recognize = |method: &method::Method, path: &str| {
+            router.recognize(method, path, &RequestParts::default()).map(|m| m.handler.route_id)
+        };
+
+        assert_eq!(recognize(&method::Get, "/files/new"), Ok("files.new".to_string()));
+        assert_eq!(recognize(&method::Get, "/files/12"), Ok("files.by_id".to_string()));
+        assert_eq!(recognize(&method::Get, "/files/report"), Ok("files.show".to_string()));
+        assert_eq!(recognize(&method::Get, "/files/a/b"), Ok("files.any".to_string()));
+        assert_eq!(recognize(&method::Post, "/files/new"), Ok("files.update".to_string()));
+        assert_eq!(recognize(&method::Put, "/files/new"), Err(Rejection::MethodNotAllowed));
+
+        router.priority("files.any", 1);
+        assert_eq!(router.recognize(&method::Get, "/files/new", &RequestParts::default()).unwrap().handler.route_id, "files.any");
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut router = Router::new();
+        router.get("/users/:id", "users.show");
+        router.get("/users/:name", "users.by_name");
+        router.post("/users/:name", "users.update");
+        router.get("/teams", "teams.index");
+        router.get("/teams", "teams.list");
+        router.route_when(method::Get, "/teams", "teams.json", vec![Guard::accept("application/json")]);
+        router.any("/any", "any.first");
+        router.any("/any", "any.second");
+        router.get("/posts/:id<int>", "posts.show");
+        router.get("/posts/:slug<alnum>", "posts.by_slug");
+        router.get("/posts/:tag<alpha>", "posts.by_tag");
+        router.get("/archive", "archive.index");
+        router.get("/archive(/page/:page)", "archive.pages");
+
+        let conflicts: Vec<_> = router.conflicts().iter()
+            .map(|c| (c.method.clone(), c.route_id.clone(), c.shadowed_route_id.clone()))
+            .collect();
+        assert_eq!(conflicts.len(), 6);
+        assert!(conflicts.contains(&(Some(method::Get), "archive.index".to_string(), "archive.pages".to_string())));
+        assert!(conflicts.contains(&(Some(method::Get), "posts.show".to_string(), "posts.by_slug".to_string())));
+        assert!(conflicts.contains(&(Some(method::Get), "posts.by_slug".to_string(), "posts.by_tag".to_string())));
+        assert!(conflicts.contains(&(Some(method::Get), "users.show".to_string(), "users.by_name".to_string())));
+        assert!(conflicts.contains(&(Some(method::Get), "teams.index".to_string(), "teams.list".to_string())));
+        assert!(conflicts.contains(&(None, "any.first".to_string(), "any.second".to_string())));
+
+        router.priority("users.by_name", 1);
+        assert_eq!(router.conflicts().len(), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid glob")]
+    fn test_invalid_glob() {
+        Router::new().get("/users/:id<float>", "users.show");
+    }
+
+    #[test]
+    fn test_remove_and_replace() {
+        let mut api = Router::new();
+        api.get("/users", "api.users");
+
+        let mut router = Router::new();
+        router.get("/users/:id", "users.show")
+            .put("/users/:id", "users.show")
+            .get("/users/:id", "users.other")
+            .any("/users/:id", "users.any")
+            .nest("/api", api);
+        router.route_when(method::Get, "/users/:id", "users.json", vec![Guard::query("json")]);
+
+        let recognize = |router: &Router, method: &method::Method, path: &str| {
+            router.recognize(method, path, &RequestParts { query: Some("json"), ..RequestParts::default() })
+                .map(|m| m.handler.route_id)
+        };
+
+        assert!(router.remove_method("users.show", &method::Get));
+        assert_eq!(recognize(&router, &method::Get, "/users/1"), Ok("users.json".to_string()));
+        assert_eq!(recognize(&router, &method::Put, "/users/1"), Ok("users.show".to_string()));
+        assert_eq!(router.glob("users.show"), Some("/users/:id"));
+
+        assert!(router.remove_method("users.show", &method::Put));
+        assert_eq!(router.glob("users.show"), None);
+        assert_eq!(recognize(&router, &method::Put, "/users/1"), Ok("users.any".to_string()));
+
+        router.replace("users.json", "/people/:id");
+        assert_eq!(router.glob("users.json"), Some("/people/:id"));
+        assert_eq!(recognize(&router, &method::Get, "/people/1"), Ok("users.json".to_string()));
+        assert_eq!(recognize(&router, &method::Get, "/users/1"), Ok("users.other".to_string()));
+
+        assert!(router.remove("users.any"));
+        assert!(router.remove("users.other"));
+        assert!(!router.remove("users.other"));
+        assert_eq!(recognize(&router, &method::Get, "/users/1"), Err(Rejection::NotFound));
+        assert!(router.matcher.get("/users/:id").is_none());
+
+        assert!(router.remove("api.users"));
+        assert!(router.nested[0].1.recognize(&method::Get, "users", &RequestParts::default()).is_err());
+        assert_eq!(router.routes(), vec![("users.json", "/people/:id")]);
+    }
+
+    #[test]
+    fn test_redirect_and_alias() {
+        let mut router = Router::new();
+        router.get("/users/:id", "users.show")
+            .put("/users/:id", "users.update")
+            .redirect("/old/:id", "users.show", status::MovedPermanently)
+            .alias("/members/:id", "users.show");
+
+        let recognize = |router: &Router, method: &method::Method, path: &str| {
+            router.recognize(method, path, &RequestParts::default())
+                .map(|m| (m.handler.route_id, m.handler.glob, m.params.find("id").map(|id| id.to_string())))
+        };
+
+        assert_eq!(recognize(&router, &method::Post, "/old/1"),
+                   Ok(("redirect:/old/:id".to_string(), "/old/:id".to_string(), Some("1".to_string()))));
+        assert_eq!(router.redirects.get("redirect:/old/:id"), Some(&("users.show".to_string(), status::MovedPermanently)));
+        assert_eq!(recognize(&router, &method::Get, "/members/2"),
+                   Ok(("users.show".to_string(), "/users/:id".to_string(), Some("2".to_string()))));
+        assert_eq!(recognize(&router, &method::Put, "/members/2"), Err(Rejection::MethodNotAllowed));
+        assert_eq!(router.glob("users.show"), Some("/users/:id"));
+
+        assert!(router.remove("users.show"));
+        assert_eq!(recognize(&router, &method::Get, "/members/2"), Err(Rejection::NotFound));
+        assert!(router.remove("redirect:/old/:id"));
+        assert!(router.redirects.is_empty());
+    }
+
+    #[test]
+    fn test_localized() {
+        let mut router = Router::new();
+        router.locales(&["en", "de"])
+            .localized(method::Get, "about", &[("en", "/about"), ("de", "/ueber-uns")])
+            .localized(method::Get, "home", &[("en", "/"), ("de", "/")]);
+
+        let recognize = |router: &Router, path: &str| {
+            router.recognize(&method::Get, path, &RequestParts::default())
+                .map(|m| (m.handler.route_id, m.handler.locale.unwrap_or_default()))
+        };
+
+        assert_eq!(recognize(&router, "/en/about"), Ok(("about".to_string(), "en".to_string())));
+        assert_eq!(recognize(&router, "/de/ueber-uns"), Ok(("about".to_string(), "de".to_string())));
+        assert_eq!(recognize(&router, "/de"), Ok(("home".to_string(), "de".to_string())));
+        assert_eq!(recognize(&router, "/de/about"), Err(Rejection::NotFound));
+        assert_eq!(router.glob("about"), Some("/en/about"));
+        assert_eq!(router.locale_prefix("de/ueber-uns"), Some("de"));
+        assert_eq!(router.locale_prefix("about"), None);
+
+        assert!(router.remove("about"));
+        assert_eq!(recognize(&router, "/de/ueber-uns"), Err(Rejection::NotFound));
+        assert!(!router.translations.contains_key("about"));
+    }
+
+    #[test]
+    fn test_sitemap() {
+        use sitemap::{ChangeFreq, SitemapEntry};
+        use url_for::UrlParams;
+
+        let mut router = Router::new();
+        router.locales(&["en", "de"])
+            .get("/", "index")
+            .get("/posts/:id", "posts.show")
+            .localized(method::Get, "about", &[("en", "/about"), ("de", "/ueber-uns")])
+            .serve_sitemap("/sitemap.xml")
+            .sitemap("index", SitemapEntry::new().changefreq(ChangeFreq::Daily).priority(1.0))
+            .sitemap("about", SitemapEntry::new())
+            .sitemap("posts.show", SitemapEntry::new().params(|| {
+                vec![UrlParams::new().with("id", "1"), UrlParams::new().with("id", "2")]
+            }));
+
+        let urls = router.sitemap_urls(|route_id, locale, params| {
+            let id = ["1", "2"].iter().find(|&&id| params == UrlParams::new().with("id", id)).map_or("-", |id| *id);
+            Url::parse(&format!("http://localhost/{}/{}/{}", locale.unwrap_or("-"), route_id, id)).unwrap()
+        });
+        let locs: Vec<&str> = urls.iter().map(|url| url.loc.as_str()).collect();
+        assert_eq!(locs.len(), 5);
+        assert_eq!(&locs[..3], &["http://localhost/en/about/-", "http://localhost/de/about/-", "http://localhost/-/index/-"]);
+        assert_eq!(&locs[3..], &["http://localhost/-/posts.show/1", "http://localhost/-/posts.show/2"]);
+        assert_eq!((urls[2].changefreq, urls[2].priority), (Some(ChangeFreq::Daily), Some(1.0)));
+
+        assert!(router.remove("sitemap:/sitemap.xml"));
+        assert_eq!(router.sitemap_route, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "list its pages with SitemapEntry::params")]
+    fn test_sitemap_without_params() {
+        Router::new().get("/posts/:id", "posts.show").sitemap("posts.show", ::sitemap::SitemapEntry::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown locale fr")]
+    fn test_localized_unknown_locale() {
+        Router::new().locales(&["en"]).localized(method::Get, "about", &[("fr", "/a-propos")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Route about is localized")]
+    fn test_replace_localized() {
+        let mut router = Router::new();
+        router.locales(&["en", "de"]).localized(method::Get, "about", &[("en", "/about"), ("de", "/ueber-uns")]);
+        assert!(!router.can_replace("about"));
+        router.replace("about", "/about-us");
+    }
+
+    #[test]
+    #[should_panic(expected = "no values for the parameters [\"id\"]")]
+    fn test_redirect_missing_params() {
+        Router::new().get("/users/:id", "users.show").redirect("/old", "users.show", status::Found);
+    }
+
+    #[test]
+    #[should_panic(expected = "passes the catch-all parameters [\"path\"]")]
+    fn test_redirect_catch_all() {
+        Router::new().get("/users/:path", "users.show").redirect("/old/*path", "users.show", status::Found);
+    }
+
+    #[test]
+    fn test_static_files() {
+        let mut router = Router::new();
+        router.static_files("/assets/*path", "public");
+
+        let matched = router.recognize(&method::Get, "/assets/css/app.css", &RequestParts::default()).unwrap();
+        assert_eq!(matched.handler.route_id, "static:/assets/*path");
+        assert_eq!(matched.params.find("path"), Some("css/app.css"));
+        assert_eq!(router.static_files.get("static:/assets/*path").unwrap().1, "path");
+        assert_eq!(router.recognize(&method::Post, "/assets/app.css", &RequestParts::default()).err(),
+                   Some(Rejection::MethodNotAllowed));
+
+        router.alias("/assets/", "static:/assets/*path");
+        let matched = router.recognize(&method::Get, "/assets/", &RequestParts::default()).unwrap();
+        assert_eq!(matched.handler.route_id, "static:/assets/*path");
+        assert_eq!(matched.params.find("path"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "has to end with a parameter")]
+    fn test_static_files_without_param() {
+        Router::new().static_files("/assets", "public");
+    }
+
+    #[test]
+    fn test_catch_panics() {
+        use std::sync::{Arc, Mutex};
+
+        let mut router = Router::new();
+        assert_eq!(router.call_handler("users", || Ok(Response::with(status::Ok))).unwrap().status, Some(status::Ok));
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let hook_reported = reported.clone();
+        router.on_panic(move |route_id, message| {
+            hook_reported.lock().unwrap().push(format!("{}: {}", route_id, message));
+        });
+
+        let err = router.call_handler("users", || panic!("No user {}", 5)).err().unwrap();
+        assert_eq!(err.response.status, Some(status::InternalServerError));
+        assert_eq!(err.error.downcast::<RouterError>(), Some(&RouterError::HandlerPanicked { route_id: "users".to_string() }));
+        assert_eq!(*reported.lock().unwrap(), vec!["users: No user 5".to_string()]);
+    }
+
+    #[test]
+    fn test_router_error() {
+        let error = RouterError::MethodNotAllowed {
+            path: "/users/1".to_string(),
+            method: method::Delete,
+            allowed: vec![method::Get, method::Head]
+        };
+        assert_eq!(error.status(), status::MethodNotAllowed);
+        assert_eq!(error.to_string(), "Method DELETE is not allowed for /users/1, allowed are GET, HEAD");
+        assert_eq!(error.problem().to_json(), concat!(
+            r#"{"type":"about:blank","title":"Method Not Allowed","status":405,"#,
+            r#""detail":"Method DELETE is not allowed for /users/1, allowed are GET, HEAD","#,
+            r#""path":"/users/1","method":"DELETE","allowed":["GET","HEAD"]}"#));
+
+        let err: IronError = error.into();
+        assert_eq!(err.response.status, Some(status::MethodNotAllowed));
+        assert_eq!(err.response.headers.get::<headers::Allow>(), Some(&headers::Allow(vec![method::Get, method::Head])));
+
+        let error = RouterError::bad_request("page", "not a number");
+        assert_eq!(error.status(), status::BadRequest);
+        assert_eq!(error.to_string(), "Invalid parameter page: not a number");
+        assert_eq!(RouterError::HandlerPanicked { route_id: "users".to_string() }.status(), status::InternalServerError);
+
+        let err: IronError = RouterError::TrailingSlash { path: "/users/".to_string() }.into();
+        assert_eq!(err.response.status, Some(status::NotFound));
+    }
+
+    #[test]
+    fn test_render_error() {
+        let mut request_headers = headers::Headers::new();
+        request_headers.set_raw("Accept", vec![b"application/json".to_vec()]);
+
+        let mut err: IronError = RouterError::NotFound { path: "/users".to_string(), method: method::Get }.into();
+        render_error(&mut err, &request_headers, "http://localhost/users".to_string());
+        assert_eq!(err.response.status, Some(status::NotFound));
+        assert_eq!(err.response.headers.get_raw("Content-Type"), Some(&[b"application/problem+json".to_vec()][..]));
+
+        let mut body = Vec::new();
+        err.response.body.unwrap().write_body(&mut body).unwrap();
+        assert_eq!(String::from_utf8(body).unwrap(), concat!(
+            r#"{"type":"about:blank","title":"Not Found","status":404,"detail":"No route found for GET /users","#,
+            r#""instance":"http://localhost/users","path":"/users","method":"GET"}"#));
+
+        let mut err = IronError::new(::std::fmt::Error, status::InternalServerError);
+        render_error(&mut err, &request_headers, "http://localhost/users".to_string());
+        assert!(err.response.body.is_none());
+
+        let mut err = IronError::new(RouterError::TrailingSlash { path: "/users/".to_string() }, status::MovedPermanently);
+        err.response.headers.set(headers::Location("/users".to_string()));
+        render_error(&mut err, &request_headers, "http://localhost/users/".to_string());
+        assert_eq!(err.response.status, Some(status::MovedPermanently));
+        assert!(err.response.body.is_none());
+    }
+
+    #[test]
+    fn test_nest() {
+        let mut api = Router::new();
+        api.get("/users/:id", "users.show");
+
+        let mut router = Router::new();
+        router.get("/", "index");
+        router.nest("/api/", api);
+
+        assert_eq!(router.route_ids.get("users.show").unwrap(), "/api/users/:id");
+        assert_eq!(router.nested[0].0, "api");
+        assert!(router.nested[0].1.recognize(&method::Get, "users/1", &RequestParts::default()).is_ok());
+
+        router.get("/api/health", "health");
+        let nested = |path| router.nested_router(&method::Get, path, &RequestParts::default()).map(|(_, _, rest)| rest);
+        assert_eq!(nested("api/users/1"), Some("users/1"));
+        assert_eq!(nested("api/health"), None);
+        assert_eq!(nested("api/unknown"), Some("unknown"));
+        assert_eq!(nested("health"), None);
+
+        let mut admin = Router::new();
+        admin.get("/stats", "admin.stats");
+        let mut api = Router::new();
+        api.get("/admin/users", "admin.users");
+        let mut router = Router::new();
+        router.nest("/api/admin", admin).nest("/api", api);
+        let nested = |path| router.nested_router(&method::Get, path, &RequestParts::default()).map(|(prefix, _, rest)| (prefix, rest));
+        assert_eq!(nested("api/admin/stats"), Some(("api/admin", "stats")));
+        assert_eq!(nested("api/admin/users"), Some(("api", "admin/users")));
+        assert_eq!(nested("api/admin/unknown"), Some(("api/admin", "unknown")));
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        assert_eq!(strip_prefix("api/users", "api"), Some("users"));
+        assert_eq!(strip_prefix("api", "api"), Some(""));
+        assert_eq!(strip_prefix("apis/users", "api"), None);
+        assert_eq!(strip_prefix("v1/api/users", "v1/api"), Some("users"));
+        assert_eq!(join_path("", "api/"), "api");
+        assert_eq!(join_path("v2", "api"), "v2/api");
+        assert_eq!(join_path("v2", ""), "v2");
     }
 
     #[test]
@@ -375,10 +2044,10 @@ mod test {
         router.post("/post", "handler");
         router.get("/post", "someone");
 
-        assert!(router.recognize(&method::Post, "/post").is_ok());
-        assert!(router.recognize(&method::Get, "/post").is_ok());
-        assert!(router.recognize(&method::Put, "/post").is_err());
-        assert!(router.recognize(&method::Get, "/post/").is_err());
+        assert!(router.recognize(&method::Post, "/post", &RequestParts::default()).is_ok());
+        assert!(router.recognize(&method::Get, "/post", &RequestParts::default()).is_ok());
+        assert!(router.recognize(&method::Put, "/post", &RequestParts::default()).is_err());
+        assert!(router.recognize(&method::Get, "/post/", &RequestParts::default()).is_err());
     }
 
     #[test]
@@ -387,12 +2056,12 @@ mod test {
         router.link(DummyController);
 
         router.put("/put", "handler");
-        match router.recognize(&method::Patch, "/patch") {
+        match router.recognize(&method::Patch, "/patch", &RequestParts::default()) {
             Ok(_) => {
                 panic!();
             },
             Err(e) => {
-                assert_eq!(RouterError::NotFound, e);
+                assert_eq!(Rejection::NotFound, e);
             }
         }
     }
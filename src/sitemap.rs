@@ -0,0 +1,141 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::url_for::UrlParams;
+
+/// The most URLs a sitemap may list, larger sitemaps are split and served with a sitemap index.
+pub const MAX_URLS: usize = 50_000;
+
+/// How often the page of a route changes, the `changefreq` of a sitemap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeFreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never
+}
+
+impl fmt::Display for ChangeFreq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ChangeFreq::Always => "always",
+            ChangeFreq::Hourly => "hourly",
+            ChangeFreq::Daily => "daily",
+            ChangeFreq::Weekly => "weekly",
+            ChangeFreq::Monthly => "monthly",
+            ChangeFreq::Yearly => "yearly",
+            ChangeFreq::Never => "never"
+        })
+    }
+}
+
+/// Returns the parameters of all pages of a route with parameters, e.g. one per blog post.
+pub type ParamsProvider = Arc<dyn Fn() -> Vec<UrlParams> + Send + Sync>;
+
+/// How a route is listed in the sitemap, see `Router::sitemap`.
+#[derive(Clone, Default)]
+pub struct SitemapEntry {
+    pub changefreq: Option<ChangeFreq>,
+    /// The priority relative to the other pages, between 0.0 and 1.0.
+    pub priority: Option<f32>,
+    pub params: Option<ParamsProvider>
+}
+
+impl SitemapEntry {
+    pub fn new() -> SitemapEntry {
+        SitemapEntry::default()
+    }
+
+    pub fn changefreq(mut self, changefreq: ChangeFreq) -> SitemapEntry {
+        self.changefreq = Some(changefreq);
+        self
+    }
+
+    pub fn priority(mut self, priority: f32) -> SitemapEntry {
+        self.priority = Some(priority.clamp(0.0, 1.0));
+        self
+    }
+
+    /// List a page for each parameters `provider` returns, it is called for every sitemap request.
+    pub fn params<F: Fn() -> Vec<UrlParams> + Send + Sync + 'static>(mut self, provider: F) -> SitemapEntry {
+        self.params = Some(Arc::new(provider));
+        self
+    }
+}
+
+/// A page listed in a sitemap.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SitemapUrl {
+    pub loc: String,
+    pub changefreq: Option<ChangeFreq>,
+    pub priority: Option<f32>
+}
+
+/// Render a sitemap of `urls`.
+pub fn urlset(urls: &[SitemapUrl]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for url in urls {
+        xml.push_str(&format!("<url><loc>{}</loc>", xml_escape(&url.loc)));
+        if let Some(changefreq) = url.changefreq {
+            xml.push_str(&format!("<changefreq>{}</changefreq>", changefreq));
+        }
+        if let Some(priority) = url.priority {
+            xml.push_str(&format!("<priority>{}</priority>", format_priority(priority)));
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// Render a sitemap index linking to the sitemaps at `locs`.
+pub fn index(locs: &[String]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for loc in locs {
+        xml.push_str(&format!("<sitemap><loc>{}</loc></sitemap>\n", xml_escape(loc)));
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+/// The priority with all its digits, and at least one decimal like `1.0`.
+fn format_priority(priority: f32) -> String {
+    let formatted = priority.to_string();
+    if formatted.contains('.') { formatted } else { formatted + ".0" }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{urlset, index, ChangeFreq, SitemapEntry, SitemapUrl};
+
+    #[test]
+    fn test_render() {
+        let urls = vec![
+            SitemapUrl { loc: "http://localhost/".to_string(), changefreq: Some(ChangeFreq::Daily), priority: Some(1.0) },
+            SitemapUrl { loc: "http://localhost/search?q=a&page=2".to_string(), changefreq: None, priority: None },
+            SitemapUrl { loc: "http://localhost/about".to_string(), changefreq: None, priority: Some(0.85) }
+        ];
+        assert_eq!(urlset(&urls), concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+            "<url><loc>http://localhost/</loc><changefreq>daily</changefreq><priority>1.0</priority></url>\n",
+            "<url><loc>http://localhost/search?q=a&amp;page=2</loc></url>\n",
+            "<url><loc>http://localhost/about</loc><priority>0.85</priority></url>\n",
+            "</urlset>\n"));
+        assert_eq!(index(&["http://localhost/sitemap.xml?page=1".to_string()]), concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+            "<sitemap><loc>http://localhost/sitemap.xml?page=1</loc></sitemap>\n",
+            "</sitemapindex>\n"));
+        assert_eq!(SitemapEntry::new().priority(1.5).priority, Some(1.0));
+    }
+}
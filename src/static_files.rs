@@ -0,0 +1,298 @@
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use iron::{headers, status, Response};
+use iron::headers::{ByteRangeSpec, ContentRangeSpec, EntityTag, HttpDate};
+use iron::mime::Mime;
+use iron::response::BodyReader;
+use time;
+use url::percent_encoding::percent_decode;
+
+/// A directory served by `Router::static_files`.
+///
+/// Files are served with `ETag` and `Last-Modified`, conditional and range requests are supported.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaticFiles {
+    dir: PathBuf,
+    index: Option<String>
+}
+
+impl StaticFiles {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> StaticFiles {
+        StaticFiles { dir: dir.into(), index: None }
+    }
+
+    /// Serve the file `name`, e.g. `index.html`, for requests to a directory.
+    ///
+    /// A catch-all parameter is never empty, so with `Router::static_files("/assets/*path", ..)` the
+    /// index of the directory itself needs an alias, e.g. `alias("/assets/", "static:/assets/*path")`.
+    pub fn index<S: Into<String>>(mut self, name: S) -> StaticFiles {
+        self.index = Some(name.into());
+        self
+    }
+
+    /// Respond with the file at `path`, the percent-encoded path below the directory.
+    ///
+    /// Returns `None` if there is no such file.
+    pub fn serve(&self, request_headers: &headers::Headers, path: &str) -> Option<Response> {
+        let mut file_path = safe_join(&self.dir, path)?;
+        if file_path.is_dir() {
+            file_path = file_path.join(self.index.as_ref()?);
+        }
+
+        let mut file = File::open(&file_path).ok()?;
+        let metadata = file.metadata().ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+
+        let len = metadata.len();
+        let modified = metadata.modified().ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        let etag = EntityTag::strong(format!("{:x}-{:x}", modified, len));
+        let last_modified = HttpDate(time::at_utc(time::Timespec::new(modified as i64, 0)));
+
+        let mut response = Response::new();
+        response.headers.set(headers::ETag(etag.clone()));
+        response.headers.set(headers::LastModified(last_modified));
+        response.headers.set(headers::AcceptRanges(vec![headers::RangeUnit::Bytes]));
+
+        if not_modified(request_headers, &etag, modified) {
+            response.status = Some(status::NotModified);
+            return Some(response);
+        }
+
+        response.headers.set(headers::ContentType(content_type(&file_path)));
+
+        let if_range = match request_headers.get::<headers::IfRange>() {
+            Some(headers::IfRange::EntityTag(tag)) => tag.strong_eq(&etag),
+            // Unlike `If-Modified-Since`, the date has to be exactly the modification date.
+            Some(headers::IfRange::Date(date)) => date.0.to_timespec().sec == modified as i64,
+            None => true
+        };
+        let range = match request_headers.get::<headers::Range>() {
+            Some(headers::Range::Bytes(specs)) if if_range => byte_range(specs, len),
+            _ => Ok(None)
+        };
+
+        match range {
+            Ok(Some((start, end))) => {
+                file.seek(SeekFrom::Start(start)).ok()?;
+                response.status = Some(status::PartialContent);
+                response.headers.set(headers::ContentRange(ContentRangeSpec::Bytes {
+                    range: Some((start, end)), instance_length: Some(len)
+                }));
+                response.headers.set(headers::ContentLength(end - start + 1));
+                response.body = Some(Box::new(BodyReader(file.take(end - start + 1))));
+            },
+            Ok(None) => {
+                response.status = Some(status::Ok);
+                response.headers.set(headers::ContentLength(len));
+                response.body = Some(Box::new(BodyReader(file)));
+            },
+            Err(()) => {
+                response.status = Some(status::RangeNotSatisfiable);
+                response.headers.remove::<headers::ContentType>();
+                response.headers.set(headers::ContentRange(ContentRangeSpec::Bytes {
+                    range: None, instance_length: Some(len)
+                }));
+            }
+        }
+
+        Some(response)
+    }
+}
+
+impl<'a> From<&'a str> for StaticFiles {
+    fn from(dir: &'a str) -> StaticFiles {
+        StaticFiles::new(dir)
+    }
+}
+
+impl<'a> From<&'a Path> for StaticFiles {
+    fn from(dir: &'a Path) -> StaticFiles {
+        StaticFiles::new(dir)
+    }
+}
+
+impl From<PathBuf> for StaticFiles {
+    fn from(dir: PathBuf) -> StaticFiles {
+        StaticFiles::new(dir)
+    }
+}
+
+/// Join the percent-encoded `path` to `dir`, `None` if it would leave `dir`.
+fn safe_join(dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut joined = dir.to_path_buf();
+
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        let segment = percent_decode(segment.as_bytes()).decode_utf8().ok()?;
+        if segment == "." || segment == ".." || segment.contains(['/', '\\', '\0'].as_ref()) ||
+            Path::new(segment.as_ref()).has_root() {
+            return None;
+        }
+        joined.push(segment.as_ref());
+    }
+
+    Some(joined)
+}
+
+/// Check `If-None-Match`, or `If-Modified-Since` if there is none.
+fn not_modified(request_headers: &headers::Headers, etag: &EntityTag, modified: u64) -> bool {
+    match request_headers.get::<headers::IfNoneMatch>() {
+        Some(headers::IfNoneMatch::Any) => true,
+        Some(headers::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        None => request_headers.get::<headers::IfModifiedSince>()
+            .is_some_and(|since| since.0 .0.to_timespec().sec >= modified as i64)
+    }
+}
+
+/// The first and last byte of a single range, `None` to send the whole file.
+///
+/// Several ranges are answered with the whole file, a range outside the file is an error.
+fn byte_range(specs: &[ByteRangeSpec], len: u64) -> Result<Option<(u64, u64)>, ()> {
+    if specs.len() != 1 {
+        return Ok(None);
+    }
+
+    let (start, end) = match specs[0] {
+        ByteRangeSpec::FromTo(start, end) => (start, end.min(len.saturating_sub(1))),
+        ByteRangeSpec::AllFrom(start) => (start, len.saturating_sub(1)),
+        ByteRangeSpec::Last(0) => return Err(()),
+        ByteRangeSpec::Last(n) => (len.saturating_sub(n), len.saturating_sub(1))
+    };
+
+    if start >= len || start > end {
+        Err(())
+    } else {
+        Ok(Some((start, end)))
+    }
+}
+
+/// The media type of a file by its extension.
+fn content_type(path: &Path) -> Mime {
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let media_type = match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "application/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        "zip" => "application/zip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream"
+    };
+
+    media_type.parse().expect("Invalid media type")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{safe_join, byte_range, content_type, StaticFiles};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use iron::{headers, status, Response};
+    use iron::headers::{ByteRangeSpec, HttpDate};
+    use time;
+
+    fn body(response: Response) -> String {
+        let mut body = Vec::new();
+        response.body.unwrap().write_body(&mut body).unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    #[test]
+    fn test_safe_join() {
+        let dir = Path::new("/srv/assets");
+        assert_eq!(safe_join(dir, "css/app.css"), Some(PathBuf::from("/srv/assets/css/app.css")));
+        assert_eq!(safe_join(dir, "my%20file.txt"), Some(PathBuf::from("/srv/assets/my file.txt")));
+        assert_eq!(safe_join(dir, "css/../../etc/passwd"), None);
+        assert_eq!(safe_join(dir, "%2e%2e/etc/passwd"), None);
+        assert_eq!(safe_join(dir, "css%2f..%2f..%2fpasswd"), None);
+        assert_eq!(safe_join(dir, "..%5c..%5cpasswd"), None);
+    }
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(byte_range(&[ByteRangeSpec::FromTo(0, 4)], 10), Ok(Some((0, 4))));
+        assert_eq!(byte_range(&[ByteRangeSpec::FromTo(5, 100)], 10), Ok(Some((5, 9))));
+        assert_eq!(byte_range(&[ByteRangeSpec::AllFrom(8)], 10), Ok(Some((8, 9))));
+        assert_eq!(byte_range(&[ByteRangeSpec::Last(3)], 10), Ok(Some((7, 9))));
+        assert_eq!(byte_range(&[ByteRangeSpec::Last(30)], 10), Ok(Some((0, 9))));
+        assert_eq!(byte_range(&[ByteRangeSpec::AllFrom(10)], 10), Err(()));
+        assert_eq!(byte_range(&[ByteRangeSpec::FromTo(0, 1), ByteRangeSpec::AllFrom(5)], 10), Ok(None));
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(content_type(Path::new("app.CSS")).to_string(), "text/css; charset=utf-8");
+        assert_eq!(content_type(Path::new("logo.png")).to_string(), "image/png");
+        assert_eq!(content_type(Path::new("data")).to_string(), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_serve() {
+        let dir = ::std::env::temp_dir().join(format!("guide-static-{}", ::std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("hello.txt"), "Hello, world").unwrap();
+        fs::write(dir.join("docs/index.html"), "<h1>Docs</h1>").unwrap();
+
+        let files = StaticFiles::new(&dir).index("index.html");
+        let response = files.serve(&headers::Headers::new(), "hello.txt").unwrap();
+        assert_eq!(response.status, Some(status::Ok));
+        assert_eq!(response.headers.get::<headers::ContentLength>(), Some(&headers::ContentLength(12)));
+        let etag = response.headers.get::<headers::ETag>().unwrap().0.clone();
+        let last_modified = response.headers.get::<headers::LastModified>().unwrap().0;
+        assert_eq!(body(response), "Hello, world");
+
+        let mut request_headers = headers::Headers::new();
+        request_headers.set(headers::IfNoneMatch::Items(vec![etag]));
+        assert_eq!(files.serve(&request_headers, "hello.txt").unwrap().status, Some(status::NotModified));
+
+        let mut request_headers = headers::Headers::new();
+        request_headers.set(headers::Range::bytes(7, 11));
+        let response = files.serve(&request_headers, "hello.txt").unwrap();
+        assert_eq!(response.status, Some(status::PartialContent));
+        assert_eq!(body(response), "world");
+
+        request_headers.set(headers::IfRange::Date(last_modified));
+        assert_eq!(files.serve(&request_headers, "hello.txt").unwrap().status, Some(status::PartialContent));
+        let later = last_modified.0.to_timespec() + time::Duration::seconds(60);
+        request_headers.set(headers::IfRange::Date(HttpDate(time::at_utc(later))));
+        assert_eq!(files.serve(&request_headers, "hello.txt").unwrap().status, Some(status::Ok));
+        request_headers.remove::<headers::IfRange>();
+
+        request_headers.set(headers::Range::bytes(20, 30));
+        assert_eq!(files.serve(&request_headers, "hello.txt").unwrap().status, Some(status::RangeNotSatisfiable));
+
+        assert_eq!(body(files.serve(&headers::Headers::new(), "docs").unwrap()), "<h1>Docs</h1>");
+        assert!(StaticFiles::new(&dir).serve(&headers::Headers::new(), "docs").is_none());
+        assert!(files.serve(&headers::Headers::new(), "missing.txt").is_none());
+        assert!(files.serve(&headers::Headers::new(), "../hello.txt").is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+use std::marker::PhantomData;
+
+use super::url_for::UrlParams;
+use super::matcher::param_name;
+
+/// A route declared once, used for both registration and link generation.
+///
+/// `P` is the type of the route parameters, `url_for` only accepts a `P` for this route,
+/// so missing or wrong parameters don't compile. Declare routes with `typed_route!`.
+pub struct TypedRoute<P> {
+    id: &'static str,
+    glob: &'static str,
+    params: &'static [&'static str],
+    _params: PhantomData<fn() -> P>
+}
+
+impl<P> TypedRoute<P> {
+    /// `params` are the names of the fields of `P`.
+    pub const fn new(id: &'static str, glob: &'static str, params: &'static [&'static str]) -> TypedRoute<P> {
+        TypedRoute { id, glob, params, _params: PhantomData }
+    }
+
+    pub fn id(&self) -> &'static str {
+        self.id
+    }
+
+    pub fn glob(&self) -> &'static str {
+        self.glob
+    }
+
+    /// Names of the glob parameters which are no fields of `P`.
+    pub fn missing_params(&self) -> Vec<&'static str> {
+        self.glob.split('/')
+            .filter_map(param_name)
+            .filter(|name| !self.params.contains(name))
+            .collect()
+    }
+}
+
+/// A route id as accepted by `url_for`, with `P` being the parameters it takes.
+pub trait RouteRef<P> {
+    fn route_id(&self) -> &str;
+}
+
+impl<P: Into<UrlParams>> RouteRef<P> for &str {
+    fn route_id(&self) -> &str {
+        self
+    }
+}
+
+impl<P: Into<UrlParams>> RouteRef<P> for &String {
+    fn route_id(&self) -> &str {
+        self
+    }
+}
+
+impl<P: Into<UrlParams>> RouteRef<P> for &TypedRoute<P> {
+    fn route_id(&self) -> &str {
+        self.id
+    }
+}
+
+/// Declare a `TypedRoute` together with the struct of its parameters.
+///
+/// ```
+/// #[macro_use] extern crate guide;
+/// extern crate iron;
+///
+/// use iron::{method, Request, Url};
+/// use guide::{Router, url_for};
+///
+/// typed_route!(pub const USER_SHOW: UserShow = ("users.show", "/users/:id") { id: u32 });
+///
+/// fn link(req: &Request) -> Url {
+///     url_for(req, &USER_SHOW, UserShow { id: 5 })
+/// }
+///
+/// fn main() {
+///     let mut router = Router::new();
+///     router.typed(method::Get, &USER_SHOW);
+/// }
+/// ```
+///
+/// Fields are converted to parameters with `to_string`, fields which are not in the glob
+/// become query parameters.
+#[macro_export]
+macro_rules! typed_route {
+    ($(#[$attr:meta])* $vis:vis const $name:ident: $params:ident = ($id:expr, $glob:expr) { $($field:ident: $ty:ty),* $(,)* }) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, PartialEq)]
+        $vis struct $params {
+            $(pub $field: $ty),*
+        }
+
+        impl From<$params> for $crate::UrlParams {
+            #[allow(unused_variables)]
+            fn from(params: $params) -> $crate::UrlParams {
+                $crate::UrlParams::new()
+                    $(.with(stringify!($field), params.$field.to_string()))*
+            }
+        }
+
+        $vis const $name: $crate::TypedRoute<$params> =
+            $crate::TypedRoute::new($id, $glob, &[$(stringify!($field)),*]);
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::RouteRef;
+    use url_for::UrlParams;
+
+    typed_route!(const USER_SHOW: UserShow = ("users.show", "/users/:id") { id: u32, tab: String });
+    typed_route!(const BROKEN: Broken = ("broken", "/teams/:team<int>/*path") { team: u32 });
+    typed_route!(const INDEX: Index = ("index", "/") {});
+
+    fn route_id<R: RouteRef<P>, P: Into<UrlParams>>(route: R, _: P) -> String {
+        route.route_id().to_string()
+    }
+
+    #[test]
+    fn test_typed_route() {
+        assert_eq!(USER_SHOW.id(), "users.show");
+        assert_eq!(USER_SHOW.glob(), "/users/:id");
+        assert!(USER_SHOW.missing_params().is_empty());
+        assert_eq!(BROKEN.missing_params(), vec!["path"]);
+
+        let params: UrlParams = UserShow { id: 5, tab: "posts".to_string() }.into();
+        assert_eq!(params, UrlParams::new().with("id", "5").with("tab", "posts"));
+        assert_eq!(UrlParams::from(Index {}), UrlParams::new());
+        assert_eq!(INDEX.glob(), "/");
+
+        assert_eq!(route_id(&USER_SHOW, UserShow { id: 5, tab: String::new() }), "users.show");
+        assert_eq!(route_id("users.show", UrlParams::new()), "users.show");
+    }
+}
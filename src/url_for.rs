@@ -3,106 +3,211 @@ use std::collections::HashMap;
 use url::Url;
 
 use iron::prelude::*;
-use super::router::RouteMap;
+use super::router::{RouteMap, BasePath, MountPath};
+use super::typed::RouteRef;
+use super::locale::{Locale, LocaleMap};
+use super::matcher::{Pattern, PathSegment};
 use mount;
 
+/// Parameters for `url_for`.
+///
+/// Parameters keep the order they were added in and a key may be added more than once,
+/// e.g. `tag=a&tag=b`. A `HashMap` converts into parameters sorted by key.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UrlParams {
+    pairs: Vec<(String, String)>,
+    keep_query: bool,
+    fragment: Option<String>
+}
+
+impl UrlParams {
+    pub fn new() -> UrlParams {
+        UrlParams::default()
+    }
+
+    /// Add a parameter, a route parameter takes the first value of its key.
+    pub fn with<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> UrlParams {
+        self.pairs.push((key.into(), value.into()));
+        self
+    }
+
+    /// Keep the query parameters of the current request, except for keys given as parameters.
+    pub fn keep_query(mut self) -> UrlParams {
+        self.keep_query = true;
+        self
+    }
+
+    /// Set the fragment of the URL, e.g. `top` for `#top`.
+    pub fn fragment<S: Into<String>>(mut self, fragment: S) -> UrlParams {
+        self.fragment = Some(fragment.into());
+        self
+    }
+
+    fn find(&self, key: &str) -> Option<&str> {
+        self.pairs.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn remove(&mut self, key: &str) -> Option<String> {
+        self.pairs.iter()
+            .position(|(k, _)| k == key)
+            .map(|i| self.pairs.remove(i).1)
+    }
+}
+
+impl From<HashMap<String, String>> for UrlParams {
+    fn from(map: HashMap<String, String>) -> UrlParams {
+        let mut pairs: Vec<(String, String)> = map.into_iter().collect();
+        pairs.sort();
+        UrlParams { pairs, ..UrlParams::default() }
+    }
+}
+
+impl From<Vec<(String, String)>> for UrlParams {
+    fn from(pairs: Vec<(String, String)>) -> UrlParams {
+        UrlParams { pairs, ..UrlParams::default() }
+    }
+}
+
 /// Generate a URL based off of the currently requested URL.
 ///
-/// The `route_id` used during route registration will be used here again.
+/// The `route_id` used during route registration will be used here again, or the `TypedRoute`
+/// the route was registered with.
 ///
 /// `params` will be inserted as route parameters if fitting, the rest will be appended as query
-/// parameters.
-pub fn url_for(request: &Request, route_id: &str, params: HashMap<String, String>) -> ::iron::Url {
+/// parameters. Values of `*param` segments may span several segments, values of `:param`
+/// segments must not contain `/`. An optional part of the glob is left out if none of its
+/// parameters is given, or all of them are given with their default.
+///
+/// Links keep the prefixes of nested routers and the version prefix the request was routed with.
+/// Routes registered with `Router::localized` link to the translation of the current `Locale`.
+pub fn url_for<R: RouteRef<P>, P: Into<UrlParams>>(request: &Request, route: R, params: P) -> ::iron::Url {
+    let locale = request.extensions.get::<Locale>().map(|l| l.locale.clone());
+    url_for_route(request, route.route_id(), locale.as_deref(), params.into())
+}
+
+/// Generate a URL like `url_for`, linking to the translation of a localized route for `locale`.
+pub fn url_for_locale<R: RouteRef<P>, P: Into<UrlParams>>(request: &Request, route: R, locale: &str, params: P) -> ::iron::Url {
+    url_for_route(request, route.route_id(), Some(locale), params.into())
+}
+
+fn url_for_route(request: &Request, route_id: &str, locale: Option<&str>, params: UrlParams) -> ::iron::Url {
     let map = request.extensions.get::<RouteMap>().expect("Couldn\'t find router set up properly.");
-    let glob = map.get(route_id).expect("No route with that ID");
+    let translation = locale.and_then(|locale| request.extensions.get::<LocaleMap>()
+        .and_then(|translations| translations.get(route_id))
+        .and_then(|translations| translations.iter().find(|(l, _)| l == locale))
+        .map(|(_, glob)| glob));
+    let glob = translation.or_else(|| map.get(route_id)).expect("No route with that ID");
 
     let mut url;
     let mut base_path: String = "".to_string();
 
     if let Some(original) = request.extensions.get::<mount::OriginalUrl>() {
         url =  original.clone();
-        let routed = request.url.clone();
-        base_path = take_base_path(url.path(), routed.path());
+        base_path = match request.extensions.get::<MountPath>() {
+            Some(mounted) => format!("{}/", mounted.trim_matches('/')),
+            None => take_base_path(url.path(), request.url.path())
+        };
     } else {
         url = request.url.clone();
     }
 
+    if let Some(base) = request.extensions.get::<BasePath>() {
+        if !base.is_empty() {
+            base_path.push_str(&format!("{}/", base));
+        }
+    }
+
     url_for_impl(url.as_mut(), glob, params, base_path);
     url
 }
 
+/// The path a mounting layer stripped from the request, found by matching the routed path
+/// against the end of the requested path.
 fn take_base_path(requested: Vec<&str>, routed: Vec<&str>) -> String {
+    // The root path has one empty segment.
+    let routed: &[&str] = if routed == [""] { &[] } else { &routed };
 
-    let mut s: String = "".to_string();
-
-    if requested == routed {
-        return s
+    if requested.len() < routed.len() || !requested.ends_with(routed) {
+        return String::new()
     }
 
-    let mut i = 0;
-
-    for t in requested {
-        if routed.len() - 1 == i {
-            break
-        }
-        if t == "" {
-            i += 1;
-            continue
-        }
-
-        if t == routed[i] {
-            break
-        } else {
-            s.push_str(&format!("{}/", t));
-        }
-
-        i += 1;
-    }
-
-    s
+    requested[..requested.len() - routed.len()].iter()
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| format!("{}/", segment))
+        .collect()
 }
 
-fn url_for_impl(url: &mut Url, glob: &str, mut params: HashMap<String, String>, base_path: String) {
+fn url_for_impl(url: &mut Url, glob: &str, mut params: UrlParams, base_path: String) {
     {
         let mut url_path_segments = url.path_segments_mut().unwrap();
         url_path_segments.clear();
         for base in base_path.split('/') {
-            if base != "" {
+            if !base.is_empty() {
                 url_path_segments.push(base);
             }
         }
-        let mut first_slash: bool = true;
-        for path_segment in glob.split('/') {
-            if path_segment.len() > 1 && (path_segment.starts_with(':') || path_segment.starts_with('*')) {
-                let key = &path_segment[1..];
-                match params.remove(key) {
-                    Some(x) => url_path_segments.push(&x),
-                    None => panic!("No value for key {}", key)
-                };
-            } else {
-                if first_slash == true && path_segment == "" {
-                    first_slash = false;
-                } else {
-                    url_path_segments.push(path_segment);
+        let pattern = Pattern::parse(glob).unwrap_or_else(|e| panic!("{}", e));
+        let path = pattern.path(|optional| {
+            // Parameters given with their default are left out of the query too.
+            optional.iter().fold(false, |include, &(key, default)| match params.find(key) {
+                Some(value) if Some(value) == default => {
+                    params.remove(key);
+                    include
+                },
+                Some(_) => true,
+                None => include
+            })
+        });
+
+        for path_segment in path {
+            match path_segment {
+                PathSegment::Static(segment) => {
+                    url_path_segments.push(segment);
+                },
+                PathSegment::Param(key, default, catch_all) => {
+                    let value = match params.remove(key).or_else(|| default.map(|d| d.to_string())) {
+                        Some(x) => x,
+                        None => panic!("No value for key {}", key)
+                    };
+
+                    if catch_all {
+                        // A catch-all parameter spans several segments.
+                        url_path_segments.extend(value.split('/'));
+                    } else if value.contains('/') {
+                        panic!("Value {:?} for key {} must not contain '/'", value, key)
+                    } else {
+                        url_path_segments.push(&value);
+                    }
                 }
             }
         }
     }
 
+    let mut query: Vec<(String, String)> = Vec::new();
+    if params.keep_query {
+        query.extend(url.query_pairs().into_owned()
+            .filter(|(key, _)| params.pairs.iter().all(|(k, _)| k != key)));
+    }
+
     // Now add on the remaining parameters that had no path match.
+    query.append(&mut params.pairs);
     url.set_query(None);
-    if !params.is_empty() {
+    if !query.is_empty() {
         url.query_pairs_mut()
-            .extend_pairs(params.into_iter());
+            .extend_pairs(query);
     }
 
-    url.set_fragment(None);
+    url.set_fragment(params.fragment.as_deref());
 }
 
 #[cfg(test)]
 mod test {
-    use super::{url_for_impl, take_base_path};
+    use super::{url_for_impl, take_base_path, UrlParams};
     use std::collections::HashMap;
+    use proptest::prelude::*;
+    use proptest::collection::vec;
 
     #[test]
     fn test_no_trailing_slash() {
@@ -111,7 +216,7 @@ mod test {
             let mut rv = HashMap::new();
             rv.insert("user".into(), "bam".into());
             rv
-        }, "".to_string());
+        }.into(), "".to_string());
         assert_eq!(url.to_string(), "http://localhost/foo/bam");
     }
 
@@ -122,7 +227,7 @@ mod test {
             let mut rv = HashMap::new();
             rv.insert("user".into(), "bam".into());
             rv
-        }, "".to_string());
+        }.into(), "".to_string());
         assert_eq!(url.to_string(), "http://localhost/foo/bam");
     }
 
@@ -133,7 +238,7 @@ mod test {
             let mut rv = HashMap::new();
             rv.insert("user".into(), "bam".into());
             rv
-        }, "".to_string());
+        }.into(), "".to_string());
         assert_eq!(url.to_string(), "http://localhost/foo/bam/");
     }
 
@@ -144,7 +249,7 @@ mod test {
             let mut rv = HashMap::new();
             rv.insert("user".into(), "bam".into());
             rv
-        }, "mounted/".to_string());
+        }.into(), "mounted/".to_string());
         assert_eq!(url.to_string(), "http://localhost/mounted/foo/bam/");
     }
 
@@ -155,13 +260,155 @@ mod test {
             let mut rv = HashMap::new();
             rv.insert("user".into(), "bam".into());
             rv
-        }, "/mounted/".to_string());
+        }.into(), "/mounted/".to_string());
         assert_eq!(url.to_string(), "http://localhost/mounted/foo/bam");
     }
 
+    #[test]
+    fn test_query_order() {
+        let mut url = "http://localhost/".parse().unwrap();
+        url_for_impl(&mut url, "/users/:id", {
+            let mut rv = HashMap::new();
+            rv.insert("id".into(), "1".into());
+            rv.insert("b".into(), "2".into());
+            rv.insert("a".into(), "1".into());
+            rv.insert("c".into(), "3".into());
+            rv
+        }.into(), "".to_string());
+        assert_eq!(url.to_string(), "http://localhost/users/1?a=1&b=2&c=3");
+    }
+
+    #[test]
+    fn test_repeated_keys_and_fragment() {
+        let mut url = "http://localhost/#old".parse().unwrap();
+        let params = UrlParams::new()
+            .with("tag", "b")
+            .with("id", "1")
+            .with("tag", "a")
+            .fragment("top");
+        url_for_impl(&mut url, "/posts", params, "".to_string());
+        assert_eq!(url.to_string(), "http://localhost/posts?tag=b&id=1&tag=a#top");
+    }
+
+    #[test]
+    fn test_keep_query() {
+        let mut url = "http://localhost/posts?sort=new&page=1&tag=x".parse().unwrap();
+        let params = UrlParams::new()
+            .with("page", "2")
+            .with("id", "5")
+            .keep_query();
+        url_for_impl(&mut url, "/posts/:id", params, "".to_string());
+        assert_eq!(url.to_string(), "http://localhost/posts/5?sort=new&tag=x&page=2");
+    }
+
+    #[test]
+    fn test_catch_all() {
+        let mut url = "http://localhost/docs/index".parse().unwrap();
+        url_for_impl(&mut url, "/docs/*path", {
+            let mut rv = HashMap::new();
+            rv.insert("path".into(), "guide/intro to routing".into());
+            rv
+        }.into(), "".to_string());
+        assert_eq!(url.to_string(), "http://localhost/docs/guide/intro%20to%20routing");
+    }
+
+    #[test]
+    fn test_param_escaped() {
+        let mut url = "http://localhost/".parse().unwrap();
+        url_for_impl(&mut url, "/users/:name", {
+            let mut rv = HashMap::new();
+            rv.insert("name".into(), "a?b#c".into());
+            rv
+        }.into(), "".to_string());
+        assert_eq!(url.to_string(), "http://localhost/users/a%3Fb%23c");
+    }
+
+    #[test]
+    #[should_panic(expected = "must not contain '/'")]
+    fn test_param_with_slash() {
+        let mut url = "http://localhost/".parse().unwrap();
+        url_for_impl(&mut url, "/users/:name", {
+            let mut rv = HashMap::new();
+            rv.insert("name".into(), "a/b".into());
+            rv
+        }.into(), "".to_string());
+    }
+
+    #[test]
+    fn test_optional_parts() {
+        let url_for = |glob: &str, params: UrlParams| {
+            let mut url = "http://localhost/".parse().unwrap();
+            url_for_impl(&mut url, glob, params, "".to_string());
+            url.to_string()
+        };
+
+        assert_eq!(url_for("/posts(/page/:page=1)", UrlParams::new()), "http://localhost/posts");
+        assert_eq!(url_for("/posts(/page/:page=1)", UrlParams::new().with("page", "1")), "http://localhost/posts");
+        assert_eq!(url_for("/posts(/page/:page=1)", UrlParams::new().with("page", "2")), "http://localhost/posts/page/2");
+        assert_eq!(url_for("/search/:q?", UrlParams::new().with("sort", "new")), "http://localhost/search?sort=new");
+        assert_eq!(url_for("/search/:q?", UrlParams::new().with("q", "rust")), "http://localhost/search/rust");
+        assert_eq!(url_for("/archive(/:year/:month=1)", UrlParams::new().with("year", "2020").with("month", "1")),
+                   "http://localhost/archive/2020/1");
+    }
+
     #[test]
     fn test_take_base_path() {
-        let s = take_base_path(vec!("mounted","foo", "bar"), vec!("foo",":biz"));
+        let s = take_base_path(vec!("mounted","foo", "bar"), vec!("foo","bar"));
         assert_eq!(s, "mounted/");
     }
+
+    #[test]
+    fn test_take_base_path_same_segment() {
+        let s = take_base_path(vec!("foo", "foo", "bar"), vec!("foo", "bar"));
+        assert_eq!(s, "foo/");
+    }
+
+    #[test]
+    fn test_take_base_path_empty() {
+        assert_eq!(take_base_path(vec!("mounted"), vec!("")), "mounted/");
+        assert_eq!(take_base_path(vec!(""), vec!("")), "");
+        assert_eq!(take_base_path(vec!("a"), vec!()), "a/");
+        assert_eq!(take_base_path(vec!("a"), vec!("b", "c")), "");
+    }
+
+    fn segment() -> BoxedStrategy<String> {
+        // A tiny alphabet, so prefixes often repeat route segments.
+        "[ab]{1,2}".boxed()
+    }
+
+    proptest! {
+        #[test]
+        fn prop_take_base_path_finds_prefix(prefix in vec(segment(), 0..4), routed in vec(segment(), 0..4),
+                                            trailing_slash in any::<bool>()) {
+            let mut routed = routed;
+            if trailing_slash || routed.is_empty() {
+                routed.push("".to_string());
+            }
+
+            let requested: Vec<&str> = prefix.iter().chain(routed.iter()).map(|s| s.as_str()).collect();
+            let routed: Vec<&str> = routed.iter().map(|s| s.as_str()).collect();
+            let expected: String = prefix.iter().map(|s| format!("{}/", s)).collect();
+
+            prop_assert_eq!(take_base_path(requested, routed), expected);
+        }
+
+        #[test]
+        fn prop_take_base_path_never_panics(requested in vec("[ab]{0,2}", 0..5), routed in vec("[ab]{0,2}", 0..5)) {
+            let requested: Vec<&str> = requested.iter().map(|s| s.as_str()).collect();
+            let routed: Vec<&str> = routed.iter().map(|s| s.as_str()).collect();
+            take_base_path(requested, routed);
+        }
+
+        #[test]
+        fn prop_url_for_keeps_mount(prefix in vec(segment(), 0..4), user in segment()) {
+            let base: String = prefix.iter().map(|s| format!("{}/", s)).collect();
+            let mut url = format!("http://localhost/{}foo/bar", base).parse().unwrap();
+            url_for_impl(&mut url, "/foo/:user", {
+                let mut rv = HashMap::new();
+                rv.insert("user".to_string(), user.clone());
+                rv
+            }.into(), base.clone());
+            prop_assert_eq!(url.to_string(), format!("http://localhost/{}foo/{}", base, user));
+        }
+    }
 }
@@ -0,0 +1,150 @@
+use iron::typemap::Key;
+use super::guard::RequestParts;
+
+/// Where the router reads the requested API version from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VersionSource {
+    /// A leading path segment like `/v2/users`, which is stripped before routing.
+    PathPrefix,
+    /// A header holding the version number, e.g. `X-Api-Version: 2`.
+    Header(String),
+    /// A vendor media type in `Accept`, e.g. `application/vnd.app.v2+json` for the vendor `app`.
+    Accept(String),
+    /// A query parameter holding the version number, e.g. `?version=2`.
+    Query(String)
+}
+
+/// The API version of the current request.
+///
+/// It is inserted into the request extensions by the router when versioning is enabled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiVersion {
+    pub version: u32,
+    /// Where the version was found, `None` if the default version was used.
+    pub source: Option<VersionSource>
+}
+
+impl Key for ApiVersion { type Value = ApiVersion; }
+
+/// Picks the API version of a request from the configured sources, in order.
+#[derive(Clone, Debug, Default)]
+pub struct VersionResolver {
+    sources: Vec<VersionSource>,
+    default: Option<u32>
+}
+
+impl VersionResolver {
+    pub fn new() -> VersionResolver {
+        VersionResolver::default()
+    }
+
+    pub fn source(&mut self, source: VersionSource) -> &mut VersionResolver {
+        self.sources.push(source);
+        self
+    }
+
+    pub fn default_version(&mut self, version: u32) -> &mut VersionResolver {
+        self.default = Some(version);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.sources.is_empty() || self.default.is_some()
+    }
+
+    /// Resolve the version of a request to `path`.
+    ///
+    /// Returns the version and the path left for routing.
+    pub fn resolve<'a>(&self, path: &'a str, parts: &RequestParts) -> (Option<ApiVersion>, &'a str) {
+        for source in &self.sources {
+            let found = match *source {
+                VersionSource::PathPrefix => {
+                    let (first, rest) = match path.find('/') {
+                        Some(i) => (&path[..i], &path[i + 1..]),
+                        None => (path, "")
+                    };
+                    match parse_version(first) {
+                        Some(version) => return (Some(ApiVersion { version, source: Some(source.clone()) }), rest),
+                        None => None
+                    }
+                },
+                VersionSource::Header(ref name) => parts.header_values(name).iter()
+                    .filter_map(|value| value.parse().ok())
+                    .next(),
+                VersionSource::Accept(ref vendor) => {
+                    let prefix = format!("application/vnd.{}.", vendor);
+                    parts.header_values("Accept").iter()
+                        .filter(|value| value.starts_with(&prefix))
+                        .filter_map(|value| parse_version(value[prefix.len()..].split('+').next().unwrap_or("")))
+                        .next()
+                },
+                VersionSource::Query(ref name) => parts.query_values(name).iter()
+                    .filter_map(|value| value.parse().ok())
+                    .next()
+            };
+
+            if let Some(version) = found {
+                return (Some(ApiVersion { version, source: Some(source.clone()) }), path);
+            }
+        }
+
+        (self.default.map(|version| ApiVersion { version, source: None }), path)
+    }
+}
+
+/// Parse `v2` into `2`.
+fn parse_version(segment: &str) -> Option<u32> {
+    if segment.len() > 1 && segment.starts_with('v') {
+        segment[1..].parse().ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{VersionResolver, VersionSource, ApiVersion};
+    use guard::RequestParts;
+    use iron::headers::Headers;
+
+    #[test]
+    fn test_path_prefix() {
+        let mut resolver = VersionResolver::new();
+        resolver.source(VersionSource::PathPrefix);
+
+        let (version, path) = resolver.resolve("v2/users/1", &RequestParts::default());
+        assert_eq!(version, Some(ApiVersion { version: 2, source: Some(VersionSource::PathPrefix) }));
+        assert_eq!(path, "users/1");
+
+        let (version, path) = resolver.resolve("v3", &RequestParts::default());
+        assert_eq!(version.unwrap().version, 3);
+        assert_eq!(path, "");
+
+        assert_eq!(resolver.resolve("users/1", &RequestParts::default()), (None, "users/1"));
+    }
+
+    #[test]
+    fn test_header_and_query() {
+        let mut resolver = VersionResolver::new();
+        resolver.source(VersionSource::Accept("app".to_string()))
+            .source(VersionSource::Header("X-Api-Version".to_string()))
+            .source(VersionSource::Query("version".to_string()))
+            .default_version(1);
+
+        let mut headers = Headers::new();
+        headers.set_raw("Accept", vec![b"text/html, application/vnd.app.v2+json".to_vec()]);
+        let parts = RequestParts { headers: Some(&headers), query: Some("version=3"), ..RequestParts::default() };
+        assert_eq!(resolver.resolve("users", &parts).0.unwrap().version, 2);
+
+        let parts = RequestParts { query: Some("version=3"), ..RequestParts::default() };
+        assert_eq!(resolver.resolve("users", &parts).0.unwrap().version, 3);
+
+        let mut headers = Headers::new();
+        headers.set_raw("X-Api-Version", vec![b"4".to_vec()]);
+        let parts = RequestParts { headers: Some(&headers), ..RequestParts::default() };
+        assert_eq!(resolver.resolve("users", &parts).0.unwrap().version, 4);
+
+        assert_eq!(resolver.resolve("users", &RequestParts::default()).0,
+                   Some(ApiVersion { version: 1, source: None }));
+    }
+}